@@ -1,36 +1,982 @@
 use super::verify_file;
-use crate::{process_csv, CmdExecutor};
+use crate::{
+    get_writer, process_csv, process_csv_cat, process_csv_decrypt, process_csv_dedupe,
+    process_csv_diff, process_csv_encrypt, process_csv_fake, process_csv_from, process_csv_group,
+    process_csv_join, process_csv_mask, process_csv_query, process_csv_sample, process_csv_schema,
+    process_csv_show, process_csv_sort, process_csv_split, process_csv_stats, process_csv_validate,
+    spawn_pager, CmdExecutor,
+};
 
-use clap::Parser;
+use clap::{ArgAction, Args, Parser};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
 use std::fmt::Display;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
 use std::str::FromStr;
 
 #[derive(Debug, Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    arg_required_else_help = true
+)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(name = "from", about = "convert JSON, YAML or NDJSON to CSV")]
+    From(CsvFromOpts),
+    #[command(name = "show", about = "show CSV as a table")]
+    Show(CsvShowOpts),
+    #[command(name = "schema", about = "infer a JSON Schema from CSV")]
+    Schema(CsvSchemaOpts),
+    #[command(name = "validate", about = "validate CSV against a JSON Schema")]
+    Validate(CsvValidateOpts),
+    #[command(name = "stats", about = "profile the columns of CSV")]
+    Stats(CsvStatsOpts),
+    #[command(
+        name = "group",
+        about = "group and aggregate CSV, optionally as a pivot table"
+    )]
+    Group(CsvGroupOpts),
+    #[command(name = "join", about = "join two CSV files on key columns")]
+    Join(CsvJoinOpts),
+    #[command(name = "diff", about = "compare two CSV snapshots by key")]
+    Diff(CsvDiffOpts),
+    #[command(name = "sort", about = "sort CSV by one or more columns")]
+    Sort(CsvSortOpts),
+    #[command(name = "dedupe", about = "drop repeated rows, keeping the first")]
+    Dedupe(CsvDedupeOpts),
+    #[command(name = "sample", about = "pick random rows of CSV")]
+    Sample(CsvSampleOpts),
+    #[command(
+        name = "split",
+        about = "split CSV into files by row count or column value"
+    )]
+    Split(CsvSplitOpts),
+    #[command(name = "cat", about = "concatenate CSV files, aligning their headers")]
+    Cat(CsvCatOpts),
+    #[command(name = "mask", about = "pseudonymize or redact sensitive CSV columns")]
+    Mask(CsvMaskOpts),
+    #[command(name = "encrypt", about = "encrypt the cells of some CSV columns")]
+    Encrypt(CsvEncryptOpts),
+    #[command(name = "decrypt", about = "decrypt the cells of some CSV columns")]
+    Decrypt(CsvDecryptOpts),
+    #[command(
+        name = "fake",
+        about = "generate synthetic CSV from a schema or a sample"
+    )]
+    Fake(CsvFakeOpts),
+    #[command(name = "query", about = "run a SQL SELECT over CSV files")]
+    Query(CsvQueryOpts),
+}
+
+// rcli csv -i input.csv --format yaml -d ';' --header false --names name,position
+// rcli csv -i input.csv --infer --type "Kit Number=int,DOB=date"
+// rcli csv -i huge.csv --format ndjson
+// cat input.csv | rcli csv -i - --output - | jq
+// rcli csv -i config.csv --format yaml --unflatten
+// rcli csv -i input.csv --format msgpack  (writes output.msgpack)
+// rcli csv -i input.csv --select Name,Position --where '"Kit Number" > 10'
+// rcli csv -i partner.csv --type "Kit Number=int" --rejects rejects.csv
+// rcli csv -i export.csv --format ndjson --jobs 0
+#[derive(Debug, Args)]
+pub struct CsvConvertOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[command(flatten)]
+    pub types: CsvTypeOpts,
 
-    #[arg(long)]
+    #[command(flatten)]
+    pub filter: CsvFilterOpts,
+
+    #[arg(long, help = "output file, - for stdout [default: output.<format>]")]
     pub output: Option<String>,
 
+    #[arg(
+        long,
+        help = "build nested objects from headers like owner.name and tags[0]"
+    )]
+    pub unflatten: bool,
+
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    #[command(flatten)]
+    pub format_opts: CsvFormatOpts,
+
+    #[arg(
+        long,
+        value_parser = parse_on_error,
+        default_value = "fail",
+        help = "fail on the first bad row or skip bad rows and report them"
+    )]
+    pub on_error: OnError,
+
+    #[arg(long, help = "write bad rows to this file, implies --on-error skip")]
+    pub rejects: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "convert on N threads, 0 for one per core"
+    )]
+    pub jobs: usize,
+}
+
+// rcli csv -i assets/juventus.csv --format sql --dialect sqlite --table players
+// --format sql holds every record in memory, the column types of the CREATE TABLE
+// are inferred from all of them before the first INSERT is written
+// rcli csv -i assets/juventus.csv --format html --standalone --title Roster --output roster.html
+#[derive(Debug, Args)]
+pub struct CsvFormatOpts {
+    #[arg(
+        long,
+        value_parser = parse_sql_dialect,
+        default_value = "postgres",
+        help = "dialect of --format sql: postgres, mysql or sqlite, sql output buffers all records"
+    )]
+    pub dialect: SqlDialect,
+
+    #[arg(long, help = "table name for sql [default: the input file name]")]
+    pub table: Option<String>,
 
-    #[arg(long, default_value_t = true)]
+    #[arg(long, help = "page title for html [default: the input file name]")]
+    pub title: Option<String>,
+
+    #[arg(long, default_value_t = 500, help = "rows per INSERT statement")]
+    pub batch_size: usize,
+
+    #[arg(long, help = "write --format html as a complete page")]
+    pub standalone: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvReadOpts {
+    // required checks explicit values only, the hidden default just lets
+    // `rcli csv <subcommand>` build the unused conversion options
+    #[arg(
+        short,
+        long,
+        value_parser = verify_file,
+        required = true,
+        default_value = "-",
+        hide_default_value = true,
+        help = "input file, - for stdin"
+    )]
+    pub input: String,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        default_value = ",",
+        help = "field delimiter, '\\t' or 'tab' for tab"
+    )]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        default_value_t = true,
+        default_missing_value = "true",
+        help = "first row is a header row, use --header false for headerless files"
+    )]
     pub header: bool,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "column names to use instead of the header row, missing ones become col_N"
+    )]
+    pub names: Vec<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        default_value = "auto",
+        help = "input encoding like utf-8, gbk or utf-16le, auto detects it"
+    )]
+    pub encoding: TextEncoding,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvTypeOpts {
+    #[arg(
+        long,
+        help = "emit int, float, bool and null (empty cell) values as native types"
+    )]
+    pub infer: bool,
+
+    #[arg(
+        long = "type",
+        value_parser = parse_column_type,
+        value_delimiter = ',',
+        help = "force column types, e.g. \"Kit Number=int,DOB=date\""
+    )]
+    pub types: Vec<(String, ColumnType)>,
+}
+
+// rcli csv from -i players.json -o players.csv --arrays index
+// curl -s api/players | rcli csv from --format ndjson -o -
+#[derive(Debug, Args)]
+pub struct CsvFromOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(
+        short,
+        long,
+        default_value = "output.csv",
+        help = "output file, - for stdout"
+    )]
+    pub output: String,
+
+    #[arg(
+        long,
+        value_parser = parse_input_format,
+        help = "json, yaml or ndjson [default: guessed from the input extension, json]"
+    )]
+    pub format: Option<InputFormat>,
+
+    #[arg(short, long, value_parser = parse_delimiter, default_value = ",")]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        value_parser = parse_array_mode,
+        default_value = "json",
+        help = "encode arrays as a json string, joined values or indexed columns like tags[0]"
+    )]
+    pub arrays: ArrayMode,
+
+    #[arg(long, default_value = ";", help = "separator used by --arrays join")]
+    pub array_separator: String,
+
+    #[arg(long, help = "write a UTF-8 BOM and CRLF line endings for Excel")]
+    pub excel: bool,
+}
+
+// rcli csv show -i assets/juventus.csv --head 10 --max-width 20
+// rcli csv show -i huge.csv --page 3 --page-size 100 --no-pager
+#[derive(Debug, Args)]
+pub struct CsvShowOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(long, help = "only show the first N records")]
+    pub head: Option<usize>,
+
+    #[arg(long, conflicts_with = "head", help = "only show the last N records")]
+    pub tail: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["head", "tail"],
+        help = "only show page N, counting from 1"
+    )]
+    pub page: Option<usize>,
+
+    #[arg(
+        long,
+        default_value_t = 50,
+        help = "records per page, column widths are fitted per page"
+    )]
+    pub page_size: usize,
+
+    #[arg(
+        long,
+        help = "print to stdout instead of piping a terminal through $PAGER"
+    )]
+    pub no_pager: bool,
+
+    #[arg(long, default_value_t = 32, help = "truncate cells wider than this")]
+    pub max_width: usize,
+
+    #[arg(
+        long,
+        help = "show one block per record, used when the table is too wide"
+    )]
+    pub vertical: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvFilterOpts {
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "columns to keep, in the given order"
+    )]
+    pub select: Vec<String>,
+
+    #[arg(
+        long = "where",
+        help = "keep rows matching the expression, e.g. 'Position == \"Forward\" && \"Kit Number\" > 10'"
+    )]
+    pub filter: Option<String>,
+}
+
+// rcli csv schema -i assets/juventus.csv -o schema.json
+#[derive(Debug, Args)]
+pub struct CsvSchemaOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "max distinct values of an enum column"
+    )]
+    pub max_enum: usize,
+}
+
+// rcli csv validate -i partner.csv --schema schema.json
+#[derive(Debug, Args)]
+pub struct CsvValidateOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+}
+
+// rcli csv stats -i assets/juventus.csv --top 3 --format json
+#[derive(Debug, Args)]
+pub struct CsvStatsOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        default_value_t = 5,
+        help = "number of most frequent values to report"
+    )]
+    pub top: usize,
+
+    #[arg(long, value_parser = parse_report_format, default_value = "table")]
+    pub format: ReportFormat,
+}
+
+// rcli csv group -i assets/juventus.csv --by Position --agg "count(*),avg(Kit Number)"
+// rcli csv group -i assets/juventus.csv --by Position --pivot Nationality --format yaml
+#[derive(Debug, Args)]
+pub struct CsvGroupOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required = true,
+        help = "columns to group by"
+    )]
+    pub by: Vec<String>,
+
+    #[arg(
+        long,
+        value_parser = parse_aggregate,
+        value_delimiter = ',',
+        default_value = "count(*)",
+        help = "aggregates: count(*), count, sum, avg, min and max of a column"
+    )]
+    pub agg: Vec<Aggregate>,
+
+    #[arg(long, help = "spread the aggregates over the values of this column")]
+    pub pivot: Option<String>,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub format_opts: CsvFormatOpts,
+}
+
+// rcli csv join roster.csv ids.csv --on Name --how left --format csv
+// rcli csv join roster.csv ids.csv --on "Name=player_name" --how full -o joined.json
+#[derive(Debug, Args)]
+pub struct CsvJoinOpts {
+    #[arg(value_parser = verify_file)]
+    pub left: String,
+
+    #[arg(value_parser = verify_file)]
+    pub right: String,
+
+    #[arg(
+        long,
+        value_parser = parse_join_key,
+        value_delimiter = ',',
+        required = true,
+        help = "key columns, use left=right when the names differ"
+    )]
+    pub on: Vec<(String, String)>,
+
+    #[arg(long, value_parser = parse_join_kind, default_value = "inner")]
+    pub how: JoinKind,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        default_value = ",",
+        help = "field delimiter of both files"
+    )]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        default_value = "auto",
+        help = "input encoding of both files, auto detects it"
+    )]
+    pub encoding: TextEncoding,
+
+    #[arg(
+        long,
+        default_value = "left_",
+        help = "prefix for left columns whose name is also on the right"
+    )]
+    pub left_prefix: String,
+
+    #[arg(
+        long,
+        default_value = "right_",
+        help = "prefix for right columns whose name is also on the left"
+    )]
+    pub right_prefix: String,
+
+    #[arg(
+        long,
+        help = "emit int, float, bool and null (empty cell) values as native types"
+    )]
+    pub infer: bool,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_format, default_value = "csv")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub format_opts: CsvFormatOpts,
+}
+
+// rcli csv diff old.csv new.csv --key Name
+// rcli csv diff old.csv new.csv --key Name,DOB --format json > patch.json
+#[derive(Debug, Args)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required = true,
+        help = "columns identifying a row"
+    )]
+    pub key: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        default_value = ",",
+        help = "field delimiter of both files"
+    )]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        default_value = "auto",
+        help = "input encoding of both files, auto detects it"
+    )]
+    pub encoding: TextEncoding,
+
+    #[arg(long, default_value_t = 32, help = "truncate cells wider than this")]
+    pub max_width: usize,
+
+    #[arg(
+        long,
+        value_parser = parse_report_format,
+        default_value = "table",
+        help = "colored table or a JSON patch list, paths have one segment per key column"
+    )]
+    pub format: ReportFormat,
+}
+
+// rcli csv sort -i assets/juventus.csv --by "Position,Kit Number:desc"
+// rcli csv sort -i huge.csv --by id --buffer-rows 1000000 -o sorted.csv
+#[derive(Debug, Args)]
+pub struct CsvSortOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        value_parser = parse_sort_key,
+        value_delimiter = ',',
+        required = true,
+        help = "sort keys like name or name:desc, numbers sort numerically"
+    )]
+    pub by: Vec<SortKey>,
+
+    #[arg(
+        long,
+        default_value_t = 100_000,
+        help = "rows kept in memory, larger inputs are sorted in chunks on disk"
+    )]
+    pub buffer_rows: usize,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv dedupe -i roster.csv --on Name,DOB
+// rcli csv dedupe -i huge.csv --buffer-rows 1000000 -o unique.csv
+#[derive(Debug, Args)]
+pub struct CsvDedupeOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "columns that identify a duplicate [default: the whole row]"
+    )]
+    pub on: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = 100_000,
+        help = "rows kept in memory, larger inputs are deduplicated on disk"
+    )]
+    pub buffer_rows: usize,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv sample -i huge.csv --rows 100 --seed 42
+// rcli csv sample -i huge.csv --fraction 0.01
+#[derive(Debug, Args)]
+pub struct CsvSampleOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        required_unless_present = "fraction",
+        help = "pick exactly N rows with reservoir sampling"
+    )]
+    pub rows: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "rows",
+        help = "keep every row with this probability"
+    )]
+    pub fraction: Option<f64>,
+
+    #[arg(long, help = "seed for a reproducible sample")]
+    pub seed: Option<u64>,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv split -i assets/juventus.csv --rows 10 --output-dir parts
+// rcli csv split -i assets/juventus.csv --by Position
+#[derive(Debug, Args)]
+pub struct CsvSplitOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(long, required_unless_present = "by", help = "max rows per file")]
+    pub rows: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "rows",
+        help = "one file per value of this column"
+    )]
+    pub by: Option<String>,
+
+    #[arg(long, default_value = ".")]
+    pub output_dir: String,
+
+    #[arg(
+        long,
+        help = "file name prefix [default: the input file name, split for stdin]"
+    )]
+    pub prefix: Option<String>,
+}
+
+// rcli csv cat 2024-*.csv --source-column file -o all.csv
+#[derive(Debug, Args)]
+pub struct CsvCatOpts {
+    #[arg(value_parser = verify_file, required = true)]
+    pub inputs: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        default_value = ",",
+        help = "field delimiter of all files"
+    )]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        default_value = "auto",
+        help = "input encoding of all files, auto detects it"
+    )]
+    pub encoding: TextEncoding,
+
+    #[arg(long, help = "add a column with the file each row came from")]
+    pub source_column: Option<String>,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv mask -i roster.csv --columns Name,DOB --key blake3.txt
+// rcli csv mask -i roster.csv --columns "Name=truncate:3,DOB=date:year" --strategy redact
+#[derive(Debug, Args)]
+pub struct CsvMaskOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        value_parser = parse_mask_column,
+        value_delimiter = ',',
+        required = true,
+        help = "columns to mask, name or name=strategy"
+    )]
+    pub columns: Vec<(String, Option<MaskStrategy>)>,
+
+    #[arg(
+        long,
+        value_parser = parse_mask_strategy,
+        default_value = "hash",
+        help = "hash, redact, truncate:N or date:year|month|decade"
+    )]
+    pub strategy: MaskStrategy,
+
+    #[arg(long, value_parser = verify_file, help = "blake3 key file, needed by hash")]
+    pub key: Option<String>,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv encrypt -i roster.csv --columns DOB,Nationality --key 0123456789abcdefghijklmnopqrstuv
+// rcli csv decrypt -i encrypted.csv --columns DOB,Nationality --key 0123456789abcdefghijklmnopqrstuv
+#[derive(Debug, Args)]
+pub struct CsvCryptOpts {
+    #[command(flatten)]
+    pub read: CsvReadOpts,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required = true,
+        help = "columns to encrypt or decrypt, the column name is authenticated with each cell"
+    )]
+    pub columns: Vec<String>,
+
+    #[arg(short, long, help = "32 bytes key, same as text encrypt")]
+    pub key: String,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvEncryptOpts {
+    #[command(flatten)]
+    pub crypt: CsvCryptOpts,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvDecryptOpts {
+    #[command(flatten)]
+    pub crypt: CsvCryptOpts,
+}
+
+// rcli csv fake --rows 10000 --schema schema.yaml --seed 42
+// rcli csv fake --rows 10000 --like assets/juventus.csv -o fixture.csv
+#[derive(Debug, Args)]
+pub struct CsvFakeOpts {
+    #[arg(long, default_value_t = 100)]
+    pub rows: usize,
+
+    #[arg(
+        long,
+        value_parser = verify_file,
+        required_unless_present = "like",
+        help = "json or yaml schema, as written by csv schema"
+    )]
+    pub schema: Option<String>,
+
+    #[arg(
+        long,
+        value_parser = verify_file,
+        conflicts_with = "schema",
+        help = "infer the schema from a sample CSV"
+    )]
+    pub like: Option<String>,
+
+    #[arg(long, help = "seed for reproducible output")]
+    pub seed: Option<u64>,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+}
+
+// rcli csv query "SELECT Position, count(*) FROM 'assets/juventus.csv' GROUP BY Position ORDER BY 2 DESC"
+// rcli csv query "SELECT p.Name, c.caps FROM 'players.csv' p LEFT JOIN 'caps.csv' c ON p.id = c.player_id" --format yaml
+#[derive(Debug, Args)]
+pub struct CsvQueryOpts {
+    #[arg(help = "SELECT statement, file names go in single quotes")]
+    pub sql: String,
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        default_value = ",",
+        help = "field delimiter of all files"
+    )]
+    pub delimiter: u8,
+
+    #[arg(
+        long,
+        value_parser = parse_encoding,
+        default_value = "auto",
+        help = "input encoding of all files, auto detects it"
+    )]
+    pub encoding: TextEncoding,
+
+    #[arg(short, long, default_value = "-", help = "output file, - for stdout")]
+    pub output: String,
+
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub format_opts: CsvFormatOpts,
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output
+        match self.cmd {
+            Some(cmd) => cmd.execute().await,
+            None => self.convert.execute().await,
+        }
+    }
+}
+
+impl CmdExecutor for Box<CsvOpts> {
+    async fn execute(self) -> anyhow::Result<()> {
+        (*self).execute().await
+    }
+}
+
+impl CmdExecutor for CsvFromOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = match self.format {
+            Some(format) => format,
+            None => InputFormat::guess(&self.input),
+        };
+        process_csv_from(&self, format)
+    }
+}
+
+impl CmdExecutor for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let stdout = std::io::stdout();
+        let pager = if self.no_pager || !stdout.is_terminal() {
+            None
+        } else {
+            spawn_pager()
+        };
+        let result = match pager {
+            Some(mut pager) => {
+                let stdin = pager.stdin.take().expect("pager stdin is piped");
+                let result = process_csv_show(&self, stdin);
+                pager.wait()?;
+                result
+            }
+            None => process_csv_show(&self, stdout.lock()),
+        };
+        // the pager was quit before the end
+        match result {
+            Err(e)
+                if e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+            {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+impl CmdExecutor for CsvSchemaOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = process_csv_schema(&self)?;
+        let mut writer = get_writer(&self.output)?;
+        serde_json::to_writer_pretty(&mut writer, &schema)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let errors = process_csv_validate(&self)?;
+        for e in &errors {
+            println!("{}", e);
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("{} validation errors in {}", errors.len(), self.read.input);
+        }
+        eprintln!("{} is valid", self.read.input);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let report = process_csv_stats(&self)?;
+        print!("{}", report);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvGroupOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_group(&self)
+    }
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_join(&self)
+    }
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        let report = process_csv_diff(&self, color)?;
+        print!("{}", report);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSortOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_sort(&self)
+    }
+}
+
+impl CmdExecutor for CsvDedupeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_dedupe(&self)
+    }
+}
+
+impl CmdExecutor for CsvSampleOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_sample(&self)
+    }
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let files = process_csv_split(&self)?;
+        eprintln!("wrote {} files", files.len());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_cat(&self)
+    }
+}
+
+impl CmdExecutor for CsvMaskOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_mask(&self)
+    }
+}
+
+impl CmdExecutor for CsvFakeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_fake(&self)
+    }
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_query(&self)
+    }
+}
+
+impl CmdExecutor for CsvEncryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_encrypt(&self.crypt)
+    }
+}
+
+impl CmdExecutor for CsvDecryptOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_decrypt(&self.crypt)
+    }
+}
+
+impl CmdExecutor for CsvConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let output = if let Some(output) = &self.output {
+            output.clone()
         } else {
             format!("output.{}", self.format)
         };
-        process_csv(&self.input, output, self.format)
+        let rejected = process_csv(&self, &output)?;
+        for e in &rejected {
+            eprintln!("{}", e);
+        }
+        if !rejected.is_empty() {
+            match &self.rejects {
+                Some(rejects) => eprintln!("{} bad rows written to {}", rejected.len(), rejects),
+                None => eprintln!("{} bad rows skipped", rejected.len()),
+            }
+        }
+        Ok(())
     }
 }
 
@@ -38,11 +984,102 @@ fn parse_format(s: &str) -> anyhow::Result<OutputFormat, anyhow::Error> {
     s.parse::<OutputFormat>()
 }
 
+fn parse_aggregate(s: &str) -> anyhow::Result<Aggregate, anyhow::Error> {
+    s.parse::<Aggregate>()
+}
+
+fn parse_join_kind(s: &str) -> anyhow::Result<JoinKind, anyhow::Error> {
+    s.parse::<JoinKind>()
+}
+
+fn parse_join_key(s: &str) -> anyhow::Result<(String, String), anyhow::Error> {
+    let (left, right) = s.split_once('=').unwrap_or((s, s));
+    let (left, right) = (left.trim(), right.trim());
+    if left.is_empty() || right.is_empty() {
+        return Err(anyhow::anyhow!(
+            "invalid join key: {}, expect name or left=right",
+            s
+        ));
+    }
+    Ok((left.into(), right.into()))
+}
+
+fn parse_sort_key(s: &str) -> anyhow::Result<SortKey, anyhow::Error> {
+    s.parse::<SortKey>()
+}
+
+fn parse_report_format(s: &str) -> anyhow::Result<ReportFormat, anyhow::Error> {
+    s.parse::<ReportFormat>()
+}
+
+fn parse_input_format(s: &str) -> anyhow::Result<InputFormat, anyhow::Error> {
+    s.parse::<InputFormat>()
+}
+
+fn parse_array_mode(s: &str) -> anyhow::Result<ArrayMode, anyhow::Error> {
+    s.parse::<ArrayMode>()
+}
+
+fn parse_encoding(s: &str) -> anyhow::Result<TextEncoding, anyhow::Error> {
+    s.parse::<TextEncoding>()
+}
+
+fn parse_sql_dialect(s: &str) -> anyhow::Result<SqlDialect, anyhow::Error> {
+    s.parse::<SqlDialect>()
+}
+
+fn parse_on_error(s: &str) -> anyhow::Result<OnError, anyhow::Error> {
+    s.parse::<OnError>()
+}
+
+fn parse_mask_strategy(s: &str) -> anyhow::Result<MaskStrategy, anyhow::Error> {
+    s.parse::<MaskStrategy>()
+}
+
+fn parse_mask_column(s: &str) -> anyhow::Result<(String, Option<MaskStrategy>), anyhow::Error> {
+    match s.split_once('=') {
+        Some((name, strategy)) if !name.trim().is_empty() => {
+            Ok((name.trim().into(), Some(strategy.trim().parse()?)))
+        }
+        None if !s.trim().is_empty() => Ok((s.trim().into(), None)),
+        _ => Err(anyhow::anyhow!(
+            "invalid mask column: {}, expect name or name=strategy",
+            s
+        )),
+    }
+}
+
+fn parse_delimiter(s: &str) -> anyhow::Result<u8, anyhow::Error> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        v if v.len() == 1 && v.is_ascii() => Ok(v.as_bytes()[0]),
+        v => Err(anyhow::anyhow!("invalid delimiter: {}", v)),
+    }
+}
+
+fn parse_column_type(s: &str) -> anyhow::Result<(String, ColumnType), anyhow::Error> {
+    match s.rsplit_once('=') {
+        Some((name, ty)) if !name.trim().is_empty() => Ok((name.trim().into(), ty.trim().parse()?)),
+        _ => Err(anyhow::anyhow!(
+            "invalid column type: {}, expect name=type",
+            s
+        )),
+    }
+}
+
 impl From<OutputFormat> for &'static str {
     fn from(format: OutputFormat) -> Self {
         match format {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Msgpack => "msgpack",
+            OutputFormat::Cbor => "cbor",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Sql => "sql",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
         }
     }
 }
@@ -51,6 +1088,14 @@ impl From<OutputFormat> for &'static str {
 pub enum OutputFormat {
     Json,
     Yaml,
+    Ndjson,
+    Toml,
+    Msgpack,
+    Cbor,
+    Csv,
+    Sql,
+    Markdown,
+    Html,
 }
 
 impl FromStr for OutputFormat {
@@ -59,6 +1104,14 @@ impl FromStr for OutputFormat {
         match s {
             "json" => Ok(OutputFormat::Json),
             "yaml" => Ok(OutputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "toml" => Ok(OutputFormat::Toml),
+            "msgpack" => Ok(OutputFormat::Msgpack),
+            "cbor" => Ok(OutputFormat::Cbor),
+            "csv" => Ok(OutputFormat::Csv),
+            "sql" => Ok(OutputFormat::Sql),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
             v => Err(anyhow::anyhow!("invalid format: {}", v)),
         }
     }
@@ -69,3 +1122,590 @@ impl Display for OutputFormat {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBucket {
+    Year,
+    Month,
+    Decade,
+}
+
+/// How `csv mask` replaces a value, empty cells are always kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaskStrategy {
+    Hash,
+    Redact,
+    Truncate(usize),
+    Date(DateBucket),
+}
+
+impl FromStr for MaskStrategy {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "hash" => Ok(MaskStrategy::Hash),
+            None if s == "redact" => Ok(MaskStrategy::Redact),
+            None if s == "truncate" => Ok(MaskStrategy::Truncate(1)),
+            Some(("truncate", n)) => Ok(MaskStrategy::Truncate(n.parse()?)),
+            Some(("date", "year")) => Ok(MaskStrategy::Date(DateBucket::Year)),
+            Some(("date", "month")) => Ok(MaskStrategy::Date(DateBucket::Month)),
+            Some(("date", "decade")) => Ok(MaskStrategy::Date(DateBucket::Decade)),
+            _ => Err(anyhow::anyhow!("invalid mask strategy: {}", s)),
+        }
+    }
+}
+
+impl Display for MaskStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaskStrategy::Hash => write!(f, "hash"),
+            MaskStrategy::Redact => write!(f, "redact"),
+            MaskStrategy::Truncate(n) => write!(f, "truncate:{}", n),
+            MaskStrategy::Date(DateBucket::Year) => write!(f, "date:year"),
+            MaskStrategy::Date(DateBucket::Month) => write!(f, "date:month"),
+            MaskStrategy::Date(DateBucket::Decade) => write!(f, "date:decade"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SqlDialect {
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+impl FromStr for SqlDialect {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "postgres" | "postgresql" => Ok(SqlDialect::Postgres),
+            "mysql" => Ok(SqlDialect::Mysql),
+            "sqlite" => Ok(SqlDialect::Sqlite),
+            v => Err(anyhow::anyhow!("invalid sql dialect: {}", v)),
+        }
+    }
+}
+
+impl From<SqlDialect> for &'static str {
+    fn from(dialect: SqlDialect) -> Self {
+        match dialect {
+            SqlDialect::Postgres => "postgres",
+            SqlDialect::Mysql => "mysql",
+            SqlDialect::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl Display for SqlDialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnError {
+    Fail,
+    Skip,
+}
+
+impl FromStr for OnError {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(OnError::Fail),
+            "skip" => Ok(OnError::Skip),
+            v => Err(anyhow::anyhow!(
+                "invalid error mode: {}, expect fail or skip",
+                v
+            )),
+        }
+    }
+}
+
+impl From<OnError> for &'static str {
+    fn from(mode: OnError) -> Self {
+        match mode {
+            OnError::Fail => "fail",
+            OnError::Skip => "skip",
+        }
+    }
+}
+
+impl Display for OnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEncoding {
+    Auto,
+    Fixed(&'static Encoding),
+}
+
+impl FromStr for TextEncoding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(TextEncoding::Auto),
+            v => Encoding::for_label(v.trim().as_bytes())
+                .map(TextEncoding::Fixed)
+                .ok_or_else(|| anyhow::anyhow!("invalid encoding: {}", v)),
+        }
+    }
+}
+
+impl From<TextEncoding> for &'static str {
+    fn from(encoding: TextEncoding) -> Self {
+        match encoding {
+            TextEncoding::Auto => "auto",
+            TextEncoding::Fixed(encoding) => encoding.name(),
+        }
+    }
+}
+
+impl Display for TextEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Ndjson,
+}
+
+impl InputFormat {
+    /// Guess the format from the file extension, defaults to json.
+    pub fn guess(input: &str) -> Self {
+        Path::new(input)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.to_ascii_lowercase().parse().ok())
+            .unwrap_or(InputFormat::Json)
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(InputFormat::Json),
+            "yaml" | "yml" => Ok(InputFormat::Yaml),
+            "ndjson" | "jsonl" => Ok(InputFormat::Ndjson),
+            v => Err(anyhow::anyhow!("invalid input format: {}", v)),
+        }
+    }
+}
+
+impl From<InputFormat> for &'static str {
+    fn from(format: InputFormat) -> Self {
+        match format {
+            InputFormat::Json => "json",
+            InputFormat::Yaml => "yaml",
+            InputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl Display for InputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// An aggregate like `avg(Kit Number)`, only count takes `*`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub func: AggFunc,
+    pub column: Option<String>,
+}
+
+impl FromStr for AggFunc {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "count" => Ok(AggFunc::Count),
+            "sum" => Ok(AggFunc::Sum),
+            "avg" | "mean" => Ok(AggFunc::Avg),
+            "min" => Ok(AggFunc::Min),
+            "max" => Ok(AggFunc::Max),
+            v => Err(anyhow::anyhow!("invalid aggregate function: {}", v)),
+        }
+    }
+}
+
+impl From<AggFunc> for &'static str {
+    fn from(func: AggFunc) -> Self {
+        match func {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+        }
+    }
+}
+
+impl Display for AggFunc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        let (func, column) = s
+            .trim()
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(|| anyhow::anyhow!("invalid aggregate: {}, expect func(column)", s))?;
+        let func: AggFunc = func.trim().parse()?;
+        let column = match column.trim() {
+            "*" if func == AggFunc::Count => None,
+            "*" | "" => return Err(anyhow::anyhow!("{} needs a column", func)),
+            c => Some(c.to_string()),
+        };
+        Ok(Aggregate { func, column })
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({})",
+            self.func,
+            self.column.as_deref().unwrap_or("*")
+        )
+    }
+}
+
+/// A sort key like `Kit Number:desc`, ascending unless `:desc` is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        let (column, descending) = match s.trim().rsplit_once(':') {
+            Some((column, order)) => match order.trim().to_ascii_lowercase().as_str() {
+                "asc" => (column, false),
+                "desc" => (column, true),
+                v => {
+                    return Err(anyhow::anyhow!(
+                        "invalid sort order: {}, expect asc or desc",
+                        v
+                    ))
+                }
+            },
+            None => (s, false),
+        };
+        let column = column.trim();
+        if column.is_empty() {
+            return Err(anyhow::anyhow!("invalid sort key: {}", s));
+        }
+        Ok(SortKey {
+            column: column.into(),
+            descending,
+        })
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let order = if self.descending { "desc" } else { "asc" };
+        write!(f, "{}:{}", self.column, order)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(ReportFormat::Table),
+            "json" => Ok(ReportFormat::Json),
+            v => Err(anyhow::anyhow!("invalid report format: {}", v)),
+        }
+    }
+}
+
+impl From<ReportFormat> for &'static str {
+    fn from(format: ReportFormat) -> Self {
+        match format {
+            ReportFormat::Table => "table",
+            ReportFormat::Json => "json",
+        }
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Full,
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "full" | "outer" => Ok(JoinKind::Full),
+            v => Err(anyhow::anyhow!("invalid join: {}", v)),
+        }
+    }
+}
+
+impl From<JoinKind> for &'static str {
+    fn from(kind: JoinKind) -> Self {
+        match kind {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "left",
+            JoinKind::Full => "full",
+        }
+    }
+}
+
+impl Display for JoinKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArrayMode {
+    Json,
+    Join,
+    Index,
+}
+
+impl FromStr for ArrayMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ArrayMode::Json),
+            "join" => Ok(ArrayMode::Join),
+            "index" => Ok(ArrayMode::Index),
+            v => Err(anyhow::anyhow!("invalid array mode: {}", v)),
+        }
+    }
+}
+
+impl From<ArrayMode> for &'static str {
+    fn from(mode: ArrayMode) -> Self {
+        match mode {
+            ArrayMode::Json => "json",
+            ArrayMode::Join => "join",
+            ArrayMode::Index => "index",
+        }
+    }
+}
+
+impl Display for ArrayMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+}
+
+impl FromStr for ColumnType {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self, Self::Err> {
+        match s {
+            "string" | "str" => Ok(ColumnType::String),
+            "int" | "integer" => Ok(ColumnType::Int),
+            "float" | "number" => Ok(ColumnType::Float),
+            "bool" | "boolean" => Ok(ColumnType::Bool),
+            "date" => Ok(ColumnType::Date),
+            v => Err(anyhow::anyhow!("invalid column type: {}", v)),
+        }
+    }
+}
+
+impl From<ColumnType> for &'static str {
+    fn from(ty: ColumnType) -> Self {
+        match ty {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+        }
+    }
+}
+
+impl Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Into::<&str>::into(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delimiter() {
+        assert_eq!(parse_delimiter(",").unwrap(), b',');
+        assert_eq!(parse_delimiter(";").unwrap(), b';');
+        assert_eq!(parse_delimiter("\t").unwrap(), b'\t');
+        assert_eq!(parse_delimiter("\\t").unwrap(), b'\t');
+        assert_eq!(parse_delimiter("tab").unwrap(), b'\t');
+        assert!(parse_delimiter(";;").is_err());
+        assert!(parse_delimiter("，").is_err());
+    }
+
+    #[test]
+    fn test_header_toggle() {
+        let opts = CsvOpts::parse_from(["csv", "-i", "-"]);
+        assert!(opts.convert.read.header);
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--header"]);
+        assert!(opts.convert.read.header);
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--header", "false"]);
+        assert!(!opts.convert.read.header);
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--names", "a,b"]);
+        assert_eq!(opts.convert.read.names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_parse_column_type() {
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--type", "Kit Number=int,DOB=date"]);
+        assert_eq!(
+            opts.convert.types.types,
+            vec![
+                ("Kit Number".to_string(), ColumnType::Int),
+                ("DOB".to_string(), ColumnType::Date)
+            ]
+        );
+        assert!(parse_column_type("Kit Number").is_err());
+        assert!(parse_column_type("=int").is_err());
+        assert!(parse_column_type("a=decimal").is_err());
+    }
+
+    #[test]
+    fn test_csv_subcommand() {
+        let opts = CsvOpts::parse_from(["csv", "from", "--arrays", "index"]);
+        match opts.cmd {
+            Some(CsvSubCommand::From(opts)) => {
+                assert_eq!(opts.input, "-");
+                assert_eq!(opts.arrays, ArrayMode::Index);
+            }
+            _ => panic!("expect csv from"),
+        }
+        assert!(CsvOpts::try_parse_from(["csv", "-i", "-", "from"]).is_err());
+        assert!(CsvOpts::try_parse_from(["csv"]).is_err());
+        assert!(CsvOpts::try_parse_from(["csv", "--format", "yaml"]).is_err());
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--format", "yaml"]);
+        assert!(opts.cmd.is_none());
+        assert_eq!(opts.convert.read.input, "-");
+    }
+
+    #[test]
+    fn test_parse_aggregate() -> anyhow::Result<()> {
+        let agg: Aggregate = "avg(Kit Number)".parse()?;
+        assert_eq!(agg.func, AggFunc::Avg);
+        assert_eq!(agg.column.as_deref(), Some("Kit Number"));
+        assert_eq!(agg.to_string(), "avg(Kit Number)");
+        assert_eq!("COUNT(*)".parse::<Aggregate>()?.to_string(), "count(*)");
+        assert!("sum(*)".parse::<Aggregate>().is_err());
+        assert!("median(x)".parse::<Aggregate>().is_err());
+        assert!("count".parse::<Aggregate>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_join_key() {
+        assert_eq!(parse_join_key("id").unwrap(), ("id".into(), "id".into()));
+        assert_eq!(
+            parse_join_key("Name = player_name").unwrap(),
+            ("Name".into(), "player_name".into())
+        );
+        assert!(parse_join_key("id=").is_err());
+    }
+
+    #[test]
+    fn test_parse_sort_key() -> anyhow::Result<()> {
+        let key: SortKey = "Kit Number:DESC".parse()?;
+        assert_eq!(key.column, "Kit Number");
+        assert!(key.descending);
+        assert_eq!("Name".parse::<SortKey>()?.to_string(), "Name:asc");
+        assert!("Name:up".parse::<SortKey>().is_err());
+        assert!(":desc".parse::<SortKey>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_encoding() -> anyhow::Result<()> {
+        assert_eq!("auto".parse::<TextEncoding>()?, TextEncoding::Auto);
+        assert_eq!("GBK".parse::<TextEncoding>()?.to_string(), "GBK");
+        assert_eq!("utf8".parse::<TextEncoding>()?.to_string(), "UTF-8");
+        assert!("klingon".parse::<TextEncoding>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mask_column() -> anyhow::Result<()> {
+        assert_eq!(parse_mask_column("Name")?, ("Name".into(), None));
+        assert_eq!(
+            parse_mask_column("DOB = date:month")?,
+            ("DOB".into(), Some(MaskStrategy::Date(DateBucket::Month)))
+        );
+        assert_eq!(
+            "truncate:3".parse::<MaskStrategy>()?,
+            MaskStrategy::Truncate(3)
+        );
+        assert_eq!(
+            "truncate".parse::<MaskStrategy>()?.to_string(),
+            "truncate:1"
+        );
+        assert!(parse_mask_column("Name=shuffle").is_err());
+        assert!("date:week".parse::<MaskStrategy>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_guess_input_format() {
+        assert_eq!(InputFormat::guess("a.json"), InputFormat::Json);
+        assert_eq!(InputFormat::guess("a.YML"), InputFormat::Yaml);
+        assert_eq!(InputFormat::guess("a.jsonl"), InputFormat::Ndjson);
+        assert_eq!(InputFormat::guess("-"), InputFormat::Json);
+    }
+}
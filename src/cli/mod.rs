@@ -31,7 +31,7 @@ pub struct Opts {
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
     #[command(name = "csv", about = "show or convert CSV")]
-    Csv(CsvOpts),
+    Csv(Box<CsvOpts>),
     #[command(name = "genpass", about = "generate password")]
     GenPass(GenPassOpts),
     #[command(subcommand)]
@@ -0,0 +1,101 @@
+use super::csv_convert::{open_reader, open_writer, read_headers, read_opts};
+use crate::cli::csv::CsvCatOpts;
+use anyhow::{bail, Result};
+use csv::StringRecord;
+
+/// Stacks the files under the union of their headers, in first-seen order.
+pub fn process_csv_cat(opts: &CsvCatOpts) -> Result<()> {
+    let mut inputs = Vec::with_capacity(opts.inputs.len());
+    let mut headers = StringRecord::new();
+    if let Some(source) = &opts.source_column {
+        headers.push_field(source);
+    }
+    for input in &opts.inputs {
+        let mut reader = open_reader(&read_opts(input, opts.delimiter, opts.encoding))?;
+        let file_headers = read_headers(&mut reader, &[])?;
+        if opts
+            .source_column
+            .as_ref()
+            .is_some_and(|s| file_headers.iter().any(|h| h == s))
+        {
+            bail!(
+                "{} already has a column named like the source column",
+                input
+            );
+        }
+        for name in &file_headers {
+            if !headers.iter().any(|h| h == name) {
+                headers.push_field(name);
+            }
+        }
+        inputs.push((input, reader, file_headers));
+    }
+
+    let mut writer = open_writer(
+        &opts.output,
+        &read_opts("-", opts.delimiter, opts.encoding),
+        &headers,
+    )?;
+    for (input, mut reader, file_headers) in inputs {
+        // position of every output column in this file
+        let positions: Vec<Option<usize>> = headers
+            .iter()
+            .map(|name| file_headers.iter().position(|h| h == name))
+            .collect();
+        for record in reader.records() {
+            let record = record?;
+            let row: StringRecord = positions
+                .iter()
+                .enumerate()
+                .map(|(i, pos)| match pos {
+                    Some(pos) => record.get(*pos).unwrap_or_default(),
+                    None if i == 0 && opts.source_column.is_some() => input.as_str(),
+                    None => "",
+                })
+                .collect();
+            writer.write_record(&row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    #[test]
+    fn test_cat_aligns_headers() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let jan = fixture.write("jan.csv", "Name,Kit Number\nBuffon,1\n")?;
+        let feb = fixture.write("feb.csv", "Kit Number,Position,Name\n10,Forward,Dybala\n")?;
+        let output = fixture.path("cat.csv");
+        let opts = csv_cmd!(
+            Cat,
+            &["cat", &jan, &feb, "--source-column", "file", "-o", &output]
+        );
+        process_csv_cat(&opts)?;
+        assert_eq!(
+            fixture.read("cat.csv")?,
+            format!(
+                "file,Name,Kit Number,Position\n{},Buffon,1,\n{},Dybala,10,Forward\n",
+                jan, feb
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_encoding() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let (gbk, _, _) = encoding_rs::GBK.encode("姓名,位置\n布冯,门将\n");
+        let input = fixture.path("gbk.csv");
+        std::fs::write(&input, gbk)?;
+        let output = fixture.path("cat.csv");
+        let opts = csv_cmd!(Cat, &["cat", &input, "--encoding", "gbk", "-o", &output]);
+        process_csv_cat(&opts)?;
+        assert_eq!(fixture.read("cat.csv")?, "姓名,位置\n布冯,门将\n");
+        Ok(())
+    }
+}
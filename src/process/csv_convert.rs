@@ -1,36 +1,745 @@
-use crate::cli::csv::OutputFormat;
-use anyhow::Result;
-use csv::Reader;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::fs;
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
-
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut result: Vec<Value> = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for record in reader.records() {
-        let rec = record?;
-        let jason_value = headers.iter().zip(rec.iter()).collect::<Value>();
-        result.push(jason_value);
-    }
-
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&result)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&result)?,
+use super::csv_encoding::decode_reader;
+use super::csv_filter::{Expr, Projection};
+use super::csv_parallel::{ordered_map, Chunk, Chunker, CHUNK_SIZE};
+use super::csv_writer::{OutputTarget, RecordWriter};
+use crate::cli::csv::{
+    ColumnType, CsvConvertOpts, CsvReadOpts, CsvTypeOpts, OnError, TextEncoding,
+};
+use crate::{get_reader, get_writer};
+use anyhow::{anyhow, bail, Result};
+use chrono::NaiveDate;
+use csv::{ByteRecord, Position, Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::io::{BufWriter, Read, Write};
+use std::thread;
+
+const SNIPPET_CHARS: usize = 60;
+const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%d.%m.%Y",
+    "%b %d, %Y",
+    "%B %d, %Y",
+    "%d %b %Y",
+];
+
+/// Turns csv records into json objects, typing the values per column.
+pub struct ValueConverter {
+    headers: StringRecord,
+    types: Vec<Option<ColumnType>>,
+    infer: bool,
+}
+
+/// Filters, projects and types the input records.
+struct RowConverter {
+    input_headers: StringRecord,
+    filter: Option<Expr>,
+    projection: Option<Projection>,
+    converter: ValueConverter,
+    unflatten: bool,
+}
+
+/// An input record after conversion, filtered out records are dropped.
+enum Converted {
+    Value(Value),
+    Rejected(ByteRecord, anyhow::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Writes converted rows, bad rows go to the rejects unless they stop the run.
+struct ConvertOutput {
+    writer: RecordWriter<BufWriter<Box<dyn Write>>>,
+    rejects: Option<Writer<Box<dyn Write>>>,
+    rejected: Vec<String>,
+    skip: bool,
+}
+
+/// Convert the csv input, returns the bad rows that were skipped.
+///
+/// With one job the input is converted record by record, so it can stream.
+/// With more the input is read in chunks of whole records that are converted
+/// on several threads, the output keeps the input order.
+pub fn process_csv(opts: &CsvConvertOpts, output: &str) -> Result<Vec<String>> {
+    let jobs = match opts.jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    if jobs <= 1 {
+        let mut reader = open_flexible_reader(&opts.read)?;
+        let input_headers = read_headers(&mut reader, &opts.read.names)?;
+        let rows = RowConverter::try_new(opts, input_headers)?;
+        let mut output = ConvertOutput::open(opts, output, &rows)?;
+        let mut record = ByteRecord::new();
+        // the reader positions are absolute already
+        while reader
+            .read_byte_record(&mut record)
+            .map_err(|e| describe_csv_error(&opts.read, e, 1, 0))?
+        {
+            if let Some(row) = rows.convert_record(&record, &opts.read) {
+                output.write(row)?;
+            }
+        }
+        return output.finish();
+    }
+
+    let input = decode_reader(get_reader(&opts.read.input)?, opts.read.encoding)?;
+    let mut chunker = Chunker::new(input, opts.read.delimiter, CHUNK_SIZE);
+    let input_headers = chunker.read_headers(&opts.read)?;
+    let rows = RowConverter::try_new(opts, input_headers)?;
+    let mut output = ConvertOutput::open(opts, output, &rows)?;
+    let skip = output.skip;
+    ordered_map(
+        jobs,
+        || chunker.next_chunk(),
+        |chunk| rows.convert_chunk(&chunk, &opts.read, skip),
+        |converted| converted.into_iter().try_for_each(|row| output.write(row)),
+    )?;
+    output.finish()
+}
+
+impl ConvertOutput {
+    fn open(opts: &CsvConvertOpts, output: &str, rows: &RowConverter) -> Result<Self> {
+        let rejects = match &opts.rejects {
+            Some(path) => Some(open_writer(path, &opts.read, &rows.input_headers)?),
+            None => None,
+        };
+        let mut writer = RecordWriter::new(BufWriter::new(get_writer(output)?), opts.format)
+            .with_target(OutputTarget::new(&opts.format_opts, &opts.read.input));
+        if !opts.unflatten {
+            writer = writer.with_columns(rows.converter.headers.iter().map(String::from).collect());
+        }
+        Ok(Self {
+            writer,
+            rejects,
+            rejected: Vec::new(),
+            skip: opts.on_error == OnError::Skip || opts.rejects.is_some(),
+        })
+    }
+
+    fn write(&mut self, row: Converted) -> Result<()> {
+        match row {
+            Converted::Value(value) => self.writer.write(&value)?,
+            Converted::Rejected(record, e) => {
+                if !self.skip {
+                    return Err(e);
+                }
+                if let Some(rejects) = &mut self.rejects {
+                    rejects.write_byte_record(&record)?;
+                }
+                self.rejected.push(e.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<String>> {
+        self.writer.finish()?;
+        if let Some(mut rejects) = self.rejects {
+            rejects.flush()?;
+        }
+        Ok(self.rejected)
+    }
+}
+
+impl RowConverter {
+    fn try_new(opts: &CsvConvertOpts, input_headers: StringRecord) -> Result<Self> {
+        let filter = match &opts.filter.filter {
+            Some(expr) => Some(Expr::parse(expr, &input_headers)?),
+            None => None,
+        };
+        let projection = if opts.filter.select.is_empty() {
+            None
+        } else {
+            Some(Projection::try_new(&input_headers, &opts.filter.select)?)
+        };
+        let headers = match &projection {
+            Some(projection) => projection.headers().clone(),
+            None => input_headers.clone(),
+        };
+        Ok(Self {
+            converter: ValueConverter::try_new(headers, &opts.types)?,
+            input_headers,
+            filter,
+            projection,
+            unflatten: opts.unflatten,
+        })
+    }
+
+    /// Convert the records of a chunk, stops at the first bad one unless `skip`.
+    fn convert_chunk(
+        &self,
+        chunk: &Chunk,
+        read: &CsvReadOpts,
+        skip: bool,
+    ) -> Result<Vec<Converted>> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(read.delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(&chunk.data[..]);
+        let mut converted = Vec::new();
+        let mut record = ByteRecord::new();
+        while reader
+            .read_byte_record(&mut record)
+            .map_err(|e| describe_csv_error(read, e, chunk.line, chunk.byte))?
+        {
+            // positions are relative to the chunk
+            let mut pos = record.position().cloned().unwrap_or_else(Position::new);
+            let (line, byte) = (chunk.line + pos.line() - 1, chunk.byte + pos.byte());
+            pos.set_line(line).set_byte(byte);
+            record.set_position(Some(pos));
+            match self.convert_record(&record, read) {
+                Some(row @ Converted::Rejected(..)) => {
+                    converted.push(row);
+                    if !skip {
+                        break;
+                    }
+                }
+                Some(row) => converted.push(row),
+                None => {}
+            }
+        }
+        Ok(converted)
+    }
+
+    /// Convert a single record, `None` when the filter drops it.
+    fn convert_record(&self, record: &ByteRecord, read: &CsvReadOpts) -> Option<Converted> {
+        match self.convert(record) {
+            Ok(value) => value.map(Converted::Value),
+            Err(e) => {
+                let e = describe_row(read, record, e);
+                Some(Converted::Rejected(record.clone(), e))
+            }
+        }
+    }
+
+    fn convert(&self, record: &ByteRecord) -> Result<Option<Value>> {
+        if record.len() != self.input_headers.len() {
+            bail!(
+                "expected {} fields, got {}",
+                self.input_headers.len(),
+                record.len()
+            );
+        }
+        let mut rec = StringRecord::from_byte_record(record.clone()).map_err(|e| {
+            let field = e.utf8_error().field() + 1;
+            anyhow!("invalid UTF-8 in field {}", field)
+        })?;
+        if self.filter.as_ref().is_some_and(|f| !f.matches(&rec)) {
+            return Ok(None);
+        }
+        if let Some(projection) = &self.projection {
+            rec = projection.apply(&rec);
+        }
+        let mut value = self.converter.convert(&rec)?;
+        if self.unflatten {
+            value = unflatten(value)?;
+        }
+        Ok(Some(value))
+    }
+}
+
+/// Point at the bad row by file, line and byte, with a snippet of the record.
+///
+/// The byte is an offset into the decoded UTF-8 input, it differs from the
+/// file offset when the input was transcoded or started with a BOM.
+fn describe_row(opts: &CsvReadOpts, record: &ByteRecord, e: anyhow::Error) -> anyhow::Error {
+    let file = input_name(opts);
+    let (line, byte) = record.position().map_or((0, 0), |p| (p.line(), p.byte()));
+    let snippet = record
+        .iter()
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(&(opts.delimiter as char).to_string());
+    let snippet = match snippet.char_indices().nth(SNIPPET_CHARS) {
+        Some((i, _)) => format!("{}…", &snippet[..i]),
+        None => snippet,
+    };
+    anyhow!(
+        "{}, line {}, decoded byte {}: {}\n    {}",
+        file,
+        line,
+        byte,
+        e,
+        snippet
+    )
+}
+
+/// Give an error of the csv reader the same context as `describe_row`, the
+/// reader position is shifted by the line and byte it started at.
+fn describe_csv_error(opts: &CsvReadOpts, e: csv::Error, line: u64, byte: u64) -> anyhow::Error {
+    let Some(pos) = e.position() else {
+        return anyhow!("{}: {}", input_name(opts), e);
     };
-    fs::write(output, content)?;
-    Ok(())
+    let (line, byte) = (line + pos.line() - 1, byte + pos.byte());
+    let reason = match e.kind() {
+        csv::ErrorKind::Io(e) => e.to_string(),
+        csv::ErrorKind::Utf8 { err, .. } => format!("invalid UTF-8 in field {}", err.field() + 1),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!("expected {} fields, got {}", expected_len, len),
+        _ => e.to_string(),
+    };
+    anyhow!(
+        "{}, line {}, decoded byte {}: {}",
+        input_name(opts),
+        line,
+        byte,
+        reason
+    )
+}
+
+fn input_name(opts: &CsvReadOpts) -> &str {
+    if opts.input == "-" {
+        "stdin"
+    } else {
+        &opts.input
+    }
+}
+
+/// Open the csv input, `-` reads from stdin.
+pub fn open_reader(opts: &CsvReadOpts) -> Result<Reader<Box<dyn Read>>> {
+    let reader = ReaderBuilder::new()
+        .delimiter(opts.delimiter)
+        .has_headers(opts.header)
+        .from_reader(decode_reader(get_reader(&opts.input)?, opts.encoding)?);
+    Ok(reader)
+}
+
+/// Like `open_reader`, but rows may have fewer or more fields than the header.
+pub fn open_flexible_reader(opts: &CsvReadOpts) -> Result<Reader<Box<dyn Read>>> {
+    let reader = ReaderBuilder::new()
+        .delimiter(opts.delimiter)
+        .has_headers(opts.header)
+        .flexible(true)
+        .from_reader(decode_reader(get_reader(&opts.input)?, opts.encoding)?);
+    Ok(reader)
+}
+
+/// CSV writer using the dialect of the input, the header row is only written
+/// when the input has one or was given column names.
+pub fn open_writer(
+    output: &str,
+    read: &CsvReadOpts,
+    headers: &StringRecord,
+) -> Result<Writer<Box<dyn Write>>> {
+    let mut writer = WriterBuilder::new()
+        .delimiter(read.delimiter)
+        .flexible(true)
+        .from_writer(get_writer(output)?);
+    if read.header || !read.names.is_empty() {
+        writer.write_record(headers)?;
+    }
+    Ok(writer)
+}
+
+/// Read options for a headered file, used by commands taking several files.
+pub fn read_opts(input: &str, delimiter: u8, encoding: TextEncoding) -> CsvReadOpts {
+    CsvReadOpts {
+        input: input.into(),
+        delimiter,
+        header: true,
+        names: Vec::new(),
+        encoding,
+    }
+}
+
+/// Resolve the column names of the reader.
+///
+/// User supplied `names` take precedence over the header row. For headerless
+/// input the width of the first record is used and unnamed columns become `col_N`.
+pub fn read_headers<R: Read>(reader: &mut Reader<R>, names: &[String]) -> Result<StringRecord> {
+    // without a header row, csv returns the first record here without consuming it
+    let width = reader.headers()?.len();
+    if names.is_empty() && reader.has_headers() {
+        return Ok(reader.headers()?.clone());
+    }
+    let headers = (0..width)
+        .map(|i| match names.get(i) {
+            Some(name) => name.clone(),
+            None => format!("col_{}", i + 1),
+        })
+        .collect();
+    Ok(headers)
+}
+
+impl ValueConverter {
+    pub fn try_new(headers: StringRecord, opts: &CsvTypeOpts) -> Result<Self> {
+        let mut types = vec![None; headers.len()];
+        for (name, ty) in &opts.types {
+            let idx = headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("unknown column: {}", name))?;
+            types[idx] = Some(*ty);
+        }
+        Ok(Self {
+            headers,
+            types,
+            infer: opts.infer,
+        })
+    }
+
+    pub fn convert(&self, record: &StringRecord) -> Result<Value> {
+        let mut obj = Map::with_capacity(self.headers.len());
+        for (i, (name, field)) in self.headers.iter().zip(record.iter()).enumerate() {
+            let value = match self.types[i] {
+                Some(ty) => {
+                    parse_value(field, ty).map_err(|e| anyhow!("column {}: {}", name, e))?
+                }
+                None if self.infer => infer_value(field),
+                None => Value::String(field.into()),
+            };
+            obj.insert(name.into(), value);
+        }
+        Ok(Value::Object(obj))
+    }
+}
+
+/// Build nested objects and arrays from dotted and indexed keys,
+/// e.g. `owner.name` and `tags[0]`.
+pub fn unflatten(value: Value) -> Result<Value> {
+    let Value::Object(flat) = value else {
+        return Ok(value);
+    };
+    let mut root = Value::Object(Map::with_capacity(flat.len()));
+    // paths holding a column value, a null one must not become a parent later
+    let mut leaves = HashSet::new();
+    for (key, value) in flat {
+        let path = parse_path(&key);
+        let conflict = || anyhow!("column {} conflicts with another column", key);
+        if (1..=path.len()).any(|n| leaves.contains(&path[..n])) {
+            return Err(conflict());
+        }
+        let mut cur = &mut root;
+        for seg in path.iter().cloned() {
+            cur = match seg {
+                PathSegment::Key(k) => {
+                    if cur.is_null() {
+                        *cur = Value::Object(Map::new());
+                    }
+                    let obj = cur.as_object_mut().ok_or_else(conflict)?;
+                    obj.entry(k).or_insert(Value::Null)
+                }
+                PathSegment::Index(n) => {
+                    if cur.is_null() {
+                        *cur = Value::Array(Vec::new());
+                    }
+                    let arr = cur.as_array_mut().ok_or_else(conflict)?;
+                    if arr.len() <= n {
+                        arr.resize(n + 1, Value::Null);
+                    }
+                    &mut arr[n]
+                }
+            };
+        }
+        if cur.is_object() || cur.is_array() {
+            return Err(conflict());
+        }
+        *cur = value;
+        leaves.insert(path);
+    }
+    Ok(root)
+}
+
+/// Split `a.b[0].c` into path segments, malformed parts are kept as plain keys.
+fn parse_path(key: &str) -> Vec<PathSegment> {
+    let mut segments = Vec::new();
+    for part in key.split('.') {
+        let (name, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        let mut indexes = Vec::new();
+        while let Some(idx) = rest
+            .strip_prefix('[')
+            .and_then(|r| r.split_once(']'))
+            .and_then(|(n, r)| n.parse::<usize>().ok().map(|n| (n, r)))
+        {
+            indexes.push(PathSegment::Index(idx.0));
+            rest = idx.1;
+        }
+        if !rest.is_empty() {
+            segments.push(PathSegment::Key(part.into()));
+            continue;
+        }
+        if !name.is_empty() || indexes.is_empty() {
+            segments.push(PathSegment::Key(name.into()));
+        }
+        segments.extend(indexes);
+    }
+    segments
+}
+
+/// Guess the json type of a csv field, falling back to string.
+pub fn infer_value(s: &str) -> Value {
+    if s.is_empty() {
+        return Value::Null;
+    }
+    if s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false") {
+        return Value::Bool(s.eq_ignore_ascii_case("true"));
+    }
+    // keep values like zip codes or ids with leading zeros as strings
+    let digits = s.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return Value::String(s.into());
+    }
+    if let Ok(v) = s.parse::<i64>() {
+        return v.into();
+    }
+    match s.parse::<f64>() {
+        Ok(v) if v.is_finite() => v.into(),
+        _ => Value::String(s.into()),
+    }
+}
+
+/// Convert a csv field into the given type, empty fields become null.
+pub fn parse_value(s: &str, ty: ColumnType) -> Result<Value> {
+    let v = s.trim();
+    if v.is_empty() {
+        return Ok(Value::Null);
+    }
+    let value = match ty {
+        ColumnType::String => Value::String(s.into()),
+        ColumnType::Int => v
+            .parse::<i64>()
+            .map_err(|_| anyhow!("invalid int: {}", s))?
+            .into(),
+        ColumnType::Float => v
+            .parse::<f64>()
+            .map_err(|_| anyhow!("invalid float: {}", s))?
+            .into(),
+        ColumnType::Bool => match v.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => Value::Bool(true),
+            "false" | "no" | "n" | "0" => Value::Bool(false),
+            _ => return Err(anyhow!("invalid bool: {}", s)),
+        },
+        ColumnType::Date => Value::String(
+            parse_date(v)
+                .ok_or_else(|| anyhow!("invalid date: {}", s))?
+                .to_string(),
+        ),
+    };
+    Ok(value)
+}
+
+/// Parse a leading date, e.g. `Apr 18, 1990 (29)` is read as 1990-04-18.
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    parse_date_format(s).map(|(date, _)| date)
+}
+
+/// Like `parse_date`, also returns the strftime format the date was written in.
+pub fn parse_date_format(s: &str) -> Option<(NaiveDate, &'static str)> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|fmt| match NaiveDate::parse_and_remainder(s, fmt) {
+            Ok((date, rest)) if rest.is_empty() || rest.starts_with(' ') => Some((date, *fmt)),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{convert_opts, Fixture};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Player {
+        name: String,
+        position: String,
+        #[serde(rename = "DOB")]
+        dob: String,
+        nationality: String,
+        #[serde(rename = "Kit Number")]
+        kit: u8,
+    }
+
+    fn reader(data: &'static str, delimiter: u8, header: bool) -> Reader<&'static [u8]> {
+        ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(header)
+            .from_reader(data.as_bytes())
+    }
+
+    #[test]
+    fn test_read_headers() -> Result<()> {
+        let mut rdr = reader("a;b\n1;2\n", b';', true);
+        assert_eq!(read_headers(&mut rdr, &[])?, vec!["a", "b"]);
+        assert_eq!(rdr.records().count(), 1);
+
+        let mut rdr = reader("1\t2\t3\n4\t5\t6\n", b'\t', false);
+        assert_eq!(
+            read_headers(&mut rdr, &[])?,
+            vec!["col_1", "col_2", "col_3"]
+        );
+        assert_eq!(rdr.records().count(), 2);
+
+        let mut rdr = reader("1|2|3\n", b'|', false);
+        let columns = vec!["x".to_string(), "y".to_string()];
+        assert_eq!(read_headers(&mut rdr, &columns)?, vec!["x", "y", "col_3"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_value() {
+        assert_eq!(infer_value(""), Value::Null);
+        assert_eq!(infer_value("10"), json!(10));
+        assert_eq!(infer_value("-3"), json!(-3));
+        assert_eq!(infer_value("1.5"), json!(1.5));
+        assert_eq!(infer_value("0.5"), json!(0.5));
+        assert_eq!(infer_value("TRUE"), json!(true));
+        assert_eq!(infer_value("007"), json!("007"));
+        assert_eq!(infer_value("NaN"), json!("NaN"));
+        assert_eq!(infer_value("Italy"), json!("Italy"));
+    }
+
+    #[test]
+    fn test_parse_value() -> Result<()> {
+        assert_eq!(parse_value("007", ColumnType::Int)?, json!(7));
+        assert_eq!(parse_value("", ColumnType::Int)?, Value::Null);
+        assert_eq!(parse_value("yes", ColumnType::Bool)?, json!(true));
+        assert_eq!(parse_value("2", ColumnType::Float)?, json!(2.0));
+        assert_eq!(parse_value("10", ColumnType::String)?, json!("10"));
+        assert_eq!(
+            parse_value("Apr 18, 1990 (29)", ColumnType::Date)?,
+            json!("1990-04-18")
+        );
+        assert!(parse_value("ten", ColumnType::Int).is_err());
+        assert!(parse_value("18/04/1990", ColumnType::Date).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_players() -> Result<()> {
+        let mut rdr = Reader::from_path("assets/juventus.csv")?;
+        let headers = read_headers(&mut rdr, &[])?;
+        let opts = CsvTypeOpts {
+            infer: true,
+            types: vec![("DOB".into(), ColumnType::Date)],
+        };
+        let converter = ValueConverter::try_new(headers, &opts)?;
+        for record in rdr.records() {
+            let value = converter.convert(&record?)?;
+            assert!(value["Kit Number"].is_u64());
+            let player: Player = serde_json::from_value(value)?;
+            assert!(player.kit > 0);
+            assert!(!player.name.is_empty() && !player.position.is_empty());
+            assert!(!player.nationality.is_empty());
+            assert!(player.dob.parse::<NaiveDate>().is_ok());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unflatten() -> Result<()> {
+        let flat = json!({
+            "id": 1,
+            "owner.name": "Agnelli",
+            "owner.email": "a@juventus.com",
+            "tags[0]": "bianconeri",
+            "tags[1]": "turin",
+            "matrix[1][0]": 3,
+            "kits[0].number": 10,
+            "kits[0].name": "Del Piero",
+            "weird]": "x"
+        });
+        assert_eq!(
+            unflatten(flat)?,
+            json!({
+                "id": 1,
+                "owner": {"name": "Agnelli", "email": "a@juventus.com"},
+                "tags": ["bianconeri", "turin"],
+                "matrix": [null, [3]],
+                "kits": [{"number": 10, "name": "Del Piero"}],
+                "weird]": "x"
+            })
+        );
+        assert!(unflatten(json!({"a": 1, "a.b": 2})).is_err());
+        assert!(unflatten(json!({"a.b": 2, "a": 1})).is_err());
+        assert!(unflatten(json!({"a[0]": 1, "a.b": 2})).is_err());
+        // an empty cell is still a value, whichever column comes first
+        assert!(unflatten(json!({"a": null, "a.b": 2})).is_err());
+        assert!(unflatten(json!({"a.b": 2, "a": null})).is_err());
+        assert!(unflatten(json!({"a[0]": null, "a[00]": 1})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_rows() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write(
+            "bad-rows.csv",
+            "Name,Kit Number\nBuffon,1\nPirlo\nTotti,ten\nDybala,10\n",
+        )?;
+        let rejects = fixture.path("rejects.csv");
+        let output = fixture.path("output.json");
+        let args = ["-i", &input, "--type", "Kit Number=int"];
+
+        let opts = convert_opts(&args);
+        let err = process_csv(&opts, &output).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "{}, line 3, decoded byte 25: expected 2 fields, got 1\n    Pirlo",
+                input
+            )
+        );
+
+        let opts = convert_opts(&[&args[..], &["--rejects", &rejects]].concat());
+        let rejected = process_csv(&opts, &output)?;
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected[1].contains("line 4, decoded byte 31: column Kit Number"));
+        assert_eq!(
+            fixture.read("rejects.csv")?,
+            "Name,Kit Number\nPirlo\nTotti,ten\n"
+        );
+        assert_eq!(
+            fixture.read_json("output.json")?,
+            json!([{"Name": "Buffon", "Kit Number": 1}, {"Name": "Dybala", "Kit Number": 10}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parallel_keeps_order() -> Result<()> {
+        use std::fmt::Write;
+
+        // spans several chunks, with newlines inside quoted fields
+        let mut data = String::from("Id,Note\n");
+        for i in 0..CHUNK_SIZE / 16 {
+            writeln!(data, "{},\"row\n{}\"", i, i)?;
+        }
+        let fixture = Fixture::new()?;
+        let input = fixture.write("parallel.csv", &data)?;
+        for jobs in ["1", "4"] {
+            let opts = convert_opts(&["-i", &input, "--format", "ndjson", "--jobs", jobs]);
+            process_csv(&opts, &fixture.path(&format!("{}.ndjson", jobs)))?;
+        }
+        let output = fixture.read("4.ndjson")?;
+        assert_eq!(output, fixture.read("1.ndjson")?);
+        assert_eq!(output.lines().count(), CHUNK_SIZE / 16);
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe_csv_error() -> Result<()> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader("a,b\nc\n".as_bytes());
+        let mut record = ByteRecord::new();
+        reader.read_byte_record(&mut record)?;
+        let e = reader.read_byte_record(&mut record).unwrap_err();
+        let opts = read_opts("-", b',', TextEncoding::Auto);
+        assert_eq!(
+            describe_csv_error(&opts, e, 10, 100).to_string(),
+            "stdin, line 11, decoded byte 104: expected 2 fields, got 1"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,162 @@
+use super::csv_convert::{open_reader, open_writer, read_headers};
+use super::csv_filter::column_index;
+use super::text::Chacha;
+use crate::cli::csv::CsvCryptOpts;
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+
+/// Every cell gets its own nonce, so equal values encrypt differently.
+/// The column name is the associated data, a cell moved to another column
+/// fails to decrypt. Empty cells are left empty.
+pub fn process_csv_encrypt(opts: &CsvCryptOpts) -> Result<()> {
+    transform_columns(opts, |chacha, cell, column| {
+        chacha.seal(cell, column.as_bytes())
+    })
+}
+
+pub fn process_csv_decrypt(opts: &CsvCryptOpts) -> Result<()> {
+    transform_columns(opts, |chacha, cell, column| {
+        chacha.open(cell, column.as_bytes())
+    })
+}
+
+fn transform_columns(
+    opts: &CsvCryptOpts,
+    f: impl Fn(&Chacha, &str, &str) -> Result<String>,
+) -> Result<()> {
+    let chacha = Chacha::try_new(&opts.key).map_err(|_| anyhow!("--key must be 32 bytes"))?;
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let columns = opts
+        .columns
+        .iter()
+        .map(|name| column_index(&headers, name))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = open_writer(&opts.output, &opts.read, &headers)?;
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let mut fields: Vec<String> = record.iter().map(String::from).collect();
+        for i in &columns {
+            match fields.get_mut(*i) {
+                Some(field) if !field.is_empty() => {
+                    *field = f(&chacha, field, &headers[*i])
+                        .map_err(|e| anyhow!("line {}, column {}: {}", line, &headers[*i], e))?;
+                }
+                _ => {}
+            }
+        }
+        writer.write_record(&StringRecord::from(fields))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv::CsvSubCommand;
+    use crate::process::test_utils::{csv_opts, Fixture};
+
+    const KEY: &str = "0123456789abcdefghijklmnopqrstuv";
+
+    fn run(args: &[&str]) -> Result<()> {
+        match csv_opts(args).cmd {
+            Some(CsvSubCommand::Encrypt(opts)) => process_csv_encrypt(&opts.crypt),
+            Some(CsvSubCommand::Decrypt(opts)) => process_csv_decrypt(&opts.crypt),
+            _ => panic!("expect csv encrypt or decrypt"),
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write(
+            "input.csv",
+            "Name,DOB,Nationality\nBuffon,\"Jan 28, 1978\",Italy\nPirlo,,Italy\n",
+        )?;
+        let encrypted = fixture.path("encrypted.csv");
+        let decrypted = fixture.path("decrypted.csv");
+        let columns = ["--columns", "DOB,Nationality", "--key", KEY];
+        run(&[&["encrypt", "-i", &input, "-o", &encrypted], &columns[..]].concat())?;
+
+        let mut reader = csv::Reader::from_path(&encrypted)?;
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(&rows[0][0], "Buffon");
+        assert_ne!(&rows[0][1], "Jan 28, 1978");
+        assert_eq!(&rows[1][1], "");
+        // same plaintext, different nonce
+        assert_ne!(&rows[0][2], &rows[1][2]);
+
+        run(&[
+            &["decrypt", "-i", &encrypted, "-o", &decrypted],
+            &columns[..],
+        ]
+        .concat())?;
+        assert_eq!(fixture.read("decrypted.csv")?, fixture.read("input.csv")?);
+
+        let wrong_key = [
+            "--columns",
+            "DOB",
+            "--key",
+            "vutsrqponmlkjihgfedcba9876543210",
+        ];
+        let err = run(&[
+            &["decrypt", "-i", &encrypted, "-o", &decrypted],
+            &wrong_key[..],
+        ]
+        .concat())
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column DOB: wrong key or corrupted ciphertext"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_name_is_authenticated() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write("input.csv", "Buffon,Italy\nPirlo,Italy\n")?;
+        let encrypted = fixture.path("encrypted.csv");
+        let headerless = ["--header", "false", "--names", "Name,Nationality"];
+        run(&[
+            &[
+                "encrypt",
+                "-i",
+                &input,
+                "-o",
+                &encrypted,
+                "--columns",
+                "Nationality",
+                "--key",
+                KEY,
+            ],
+            &headerless[..],
+        ]
+        .concat())?;
+        let content = fixture.read("encrypted.csv")?;
+        assert!(content.starts_with("Name,Nationality\nBuffon,"));
+
+        // the same cell under another column name does not decrypt
+        let moved = fixture.write("moved.csv", &content.replace("Nationality", "Country"))?;
+        let err = run(&[
+            "decrypt",
+            "-i",
+            &moved,
+            "-o",
+            &encrypted,
+            "--columns",
+            "Country",
+            "--key",
+            KEY,
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column Country: wrong key or corrupted ciphertext"
+        );
+        Ok(())
+    }
+}
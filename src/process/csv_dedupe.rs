@@ -0,0 +1,111 @@
+use super::csv_convert::{open_reader, open_writer, read_headers};
+use super::csv_filter::column_index;
+use super::csv_sort::ExternalSort;
+use crate::cli::csv::CsvDedupeOpts;
+use anyhow::Result;
+use csv::StringRecord;
+use std::cmp::Ordering;
+
+/// Keeps the first row of every key, in input order. Rows are tagged with
+/// their position, sorted by key to drop adjacent duplicates, then sorted back
+/// by position, both sorts spill to disk beyond `buffer_rows`.
+pub fn process_csv_dedupe(opts: &CsvDedupeOpts) -> Result<()> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let columns = opts
+        .on
+        .iter()
+        .map(|name| column_index(&headers, name))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut by_key = ExternalSort::new(opts.buffer_rows, |a: &StringRecord, b: &StringRecord| {
+        compare_keys(a, b, &columns).then_with(|| a[0].cmp(&b[0]))
+    });
+    for (n, record) in reader.records().enumerate() {
+        // zero padded so positions compare as text
+        let mut tagged = StringRecord::from(vec![format!("{:020}", n)]);
+        tagged.extend(record?.iter());
+        by_key.push(tagged)?;
+    }
+
+    let mut by_position =
+        ExternalSort::new(opts.buffer_rows, |a: &StringRecord, b: &StringRecord| {
+            a[0].cmp(&b[0])
+        });
+    let mut last: Option<StringRecord> = None;
+    for row in by_key.finish()? {
+        let row = row?;
+        if last
+            .as_ref()
+            .is_some_and(|last| compare_keys(last, &row, &columns) == Ordering::Equal)
+        {
+            continue;
+        }
+        by_position.push(row.clone())?;
+        last = Some(row);
+    }
+
+    let mut writer = open_writer(&opts.output, &opts.read, &headers)?;
+    for row in by_position.finish()? {
+        writer.write_record(row?.iter().skip(1))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Compare the keys of two tagged rows exactly, the whole row when `columns` is empty.
+fn compare_keys(a: &StringRecord, b: &StringRecord, columns: &[usize]) -> Ordering {
+    if columns.is_empty() {
+        return a.iter().skip(1).cmp(b.iter().skip(1));
+    }
+    columns
+        .iter()
+        .map(|i| cell(a, *i))
+        .cmp(columns.iter().map(|i| cell(b, *i)))
+}
+
+// the position tag shifts the columns by one
+fn cell(row: &StringRecord, i: usize) -> &str {
+    row.get(i + 1).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn dedupe(args: &[&str]) -> Result<Vec<String>> {
+        let fixture = Fixture::new()?;
+        let output = fixture.path("dedupe.csv");
+        let argv = [
+            &["dedupe", "-i", "assets/juventus.csv", "-o", &output],
+            args,
+        ]
+        .concat();
+        process_csv_dedupe(&csv_cmd!(Dedupe, &argv))?;
+        fixture.read_lines("dedupe.csv")
+    }
+
+    #[test]
+    fn test_dedupe() -> Result<()> {
+        let lines = dedupe(&["--on", "Position"])?;
+        assert_eq!(lines.len(), 11);
+        assert!(lines[1].starts_with("Wojciech Szczesny,Goalkeeper"));
+        assert_eq!(dedupe(&["--on", "Position", "--buffer-rows", "2"])?, lines);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe_whole_rows_on_disk() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write("in.csv", "a,b\n1,x\n1.0,x\n1,x\n2,y\n1.0,x\n")?;
+        let output = fixture.path("out.csv");
+        let argv = ["dedupe", "-i", &input, "-o", &output, "--buffer-rows", "1"];
+        process_csv_dedupe(&csv_cmd!(Dedupe, &argv))?;
+        assert_eq!(
+            fixture.read_lines("out.csv")?,
+            ["a,b", "1,x", "1.0,x", "2,y"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use super::csv_convert::{open_flexible_reader, read_headers, read_opts};
+use super::csv_filter::column_index;
+use super::csv_show::render_table;
+use crate::cli::csv::{CsvDiffOpts, ReportFormat};
+use anyhow::{bail, Result};
+use csv::StringRecord;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added {
+        key: Vec<String>,
+        row: Map<String, Value>,
+    },
+    Removed {
+        key: Vec<String>,
+        row: Map<String, Value>,
+    },
+    Changed {
+        key: Vec<String>,
+        column: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+pub fn process_csv_diff(opts: &CsvDiffOpts, color: bool) -> Result<String> {
+    let changes = diff(opts)?;
+    match opts.format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(&to_patch(&changes))? + "\n"),
+        ReportFormat::Table => Ok(render_changes(&changes, opts.max_width, color)),
+    }
+}
+
+/// Changed and added rows in the order of the new file, then removed rows.
+fn diff(opts: &CsvDiffOpts) -> Result<Vec<Change>> {
+    // exporters often drop trailing empty cells, short rows are padded when compared
+    let mut old_reader =
+        open_flexible_reader(&read_opts(&opts.old, opts.delimiter, opts.encoding))?;
+    let old_headers = read_headers(&mut old_reader, &[])?;
+    let mut new_reader =
+        open_flexible_reader(&read_opts(&opts.new, opts.delimiter, opts.encoding))?;
+    let new_headers = read_headers(&mut new_reader, &[])?;
+    let old_keys = key_columns(&old_headers, &opts.key)?;
+    let new_keys = key_columns(&new_headers, &opts.key)?;
+
+    // columns of both files, compared by name
+    let mut columns: Vec<(String, Option<usize>, Option<usize>)> = Vec::new();
+    for (i, name) in old_headers.iter().enumerate() {
+        let new = new_headers.iter().position(|h| h == name);
+        columns.push((name.into(), Some(i), new));
+    }
+    for (i, name) in new_headers.iter().enumerate() {
+        if !old_headers.iter().any(|h| h == name) {
+            columns.push((name.into(), None, Some(i)));
+        }
+    }
+    columns.retain(|(name, _, _)| !opts.key.contains(name));
+
+    let mut old_rows = Vec::new();
+    let mut index = HashMap::new();
+    for record in old_reader.records() {
+        let record = record?;
+        let key = key_of(&record, &old_keys);
+        if index.insert(key.clone(), old_rows.len()).is_some() {
+            bail!("duplicate key {} in {}", key.join(","), opts.old);
+        }
+        old_rows.push(record);
+    }
+
+    let mut matched = vec![false; old_rows.len()];
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    for record in new_reader.records() {
+        let record = record?;
+        let key = key_of(&record, &new_keys);
+        if !seen.insert(key.clone()) {
+            bail!("duplicate key {} in {}", key.join(","), opts.new);
+        }
+        let Some(i) = index.get(&key) else {
+            let row = to_row(&new_headers, &record);
+            changes.push(Change::Added { key, row });
+            continue;
+        };
+        matched[*i] = true;
+        for (column, old, new) in &columns {
+            // a short row has empty cells, only a missing column is None
+            let old = old.map(|c| old_rows[*i].get(c).unwrap_or_default());
+            let new = new.map(|c| record.get(c).unwrap_or_default());
+            if old != new {
+                changes.push(Change::Changed {
+                    key: key.clone(),
+                    column: column.clone(),
+                    old: old.map(String::from),
+                    new: new.map(String::from),
+                });
+            }
+        }
+    }
+    for (row, _) in old_rows.iter().zip(&matched).filter(|(_, m)| !**m) {
+        changes.push(Change::Removed {
+            key: key_of(row, &old_keys),
+            row: to_row(&old_headers, row),
+        });
+    }
+    Ok(changes)
+}
+
+fn key_columns(headers: &StringRecord, key: &[String]) -> Result<Vec<usize>> {
+    key.iter().map(|name| column_index(headers, name)).collect()
+}
+
+fn key_of(record: &StringRecord, keys: &[usize]) -> Vec<String> {
+    keys.iter()
+        .map(|i| record.get(*i).unwrap_or_default().to_string())
+        .collect()
+}
+
+fn to_row(headers: &StringRecord, record: &StringRecord) -> Map<String, Value> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (h.to_string(), json!(record.get(i).unwrap_or_default())))
+        .collect()
+}
+
+/// RFC 6902 operations on an object of rows by key, nested one level per key
+/// column, every remove and replace is preceded by a `test` op carrying the old value.
+fn to_patch(changes: &[Change]) -> Vec<Value> {
+    let path = |key: &[String], column: Option<&str>| {
+        let mut path: String = key
+            .iter()
+            .map(|k| "/".to_string() + &escape_pointer(k))
+            .collect();
+        if let Some(column) = column {
+            path = path + "/" + &escape_pointer(column);
+        }
+        path
+    };
+    let mut ops = Vec::new();
+    for change in changes {
+        match change {
+            Change::Added { key, row } => {
+                ops.push(json!({"op": "add", "path": path(key, None), "value": row}));
+            }
+            Change::Removed { key, row } => {
+                let path = path(key, None);
+                ops.push(json!({"op": "test", "path": path, "value": row}));
+                ops.push(json!({"op": "remove", "path": path}));
+            }
+            Change::Changed {
+                key,
+                column,
+                old,
+                new,
+            } => {
+                let path = path(key, Some(column));
+                if let Some(old) = old {
+                    ops.push(json!({"op": "test", "path": path, "value": old}));
+                }
+                match (old, new) {
+                    (_, None) => ops.push(json!({"op": "remove", "path": path})),
+                    (None, Some(new)) => ops.push(json!({"op": "add", "path": path, "value": new})),
+                    (Some(_), Some(new)) => {
+                        ops.push(json!({"op": "replace", "path": path, "value": new}))
+                    }
+                }
+            }
+        }
+    }
+    ops
+}
+
+fn escape_pointer(s: &str) -> String {
+    s.replace('~', "~0").replace('/', "~1")
+}
+
+fn render_changes(changes: &[Change], max_width: usize, color: bool) -> String {
+    if changes.is_empty() {
+        return "no differences\n".into();
+    }
+    let count = |f: fn(&Change) -> bool| changes.iter().filter(|c| f(c)).count();
+    let summary = format!(
+        "{} added, {} removed, {} changed cells\n",
+        count(|c| matches!(c, Change::Added { .. })),
+        count(|c| matches!(c, Change::Removed { .. })),
+        count(|c| matches!(c, Change::Changed { .. })),
+    );
+
+    let values = |row: &Map<String, Value>| {
+        row.values()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let headers = StringRecord::from(vec!["", "key", "column", "old", "new"]);
+    let rows: Vec<StringRecord> = changes
+        .iter()
+        .map(|change| match change {
+            Change::Added { key, row } => {
+                StringRecord::from(vec!["+", &key.join(","), "", "", &values(row)])
+            }
+            Change::Removed { key, row } => {
+                StringRecord::from(vec!["-", &key.join(","), "", &values(row), ""])
+            }
+            Change::Changed {
+                key,
+                column,
+                old,
+                new,
+            } => StringRecord::from(vec![
+                "~",
+                &key.join(","),
+                column,
+                old.as_deref().unwrap_or_default(),
+                new.as_deref().unwrap_or_default(),
+            ]),
+        })
+        .collect();
+    let table = render_table(&headers, rows.iter(), max_width);
+    if !color {
+        return summary + &table;
+    }
+
+    // the first two lines are the header and the rule
+    let mut out = summary;
+    for (i, line) in table.lines().enumerate() {
+        let code = match i.checked_sub(2).map(|i| &changes[i]) {
+            Some(Change::Added { .. }) => GREEN,
+            Some(Change::Removed { .. }) => RED,
+            Some(Change::Changed { .. }) => YELLOW,
+            None => "",
+        };
+        if code.is_empty() {
+            out += line;
+        } else {
+            out += &format!("{}{}{}", code, line, RESET);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn diff(old: &str, new: &str, args: &[&str], color: bool) -> Result<String> {
+        let fixture = Fixture::new()?;
+        let old = fixture.write("old.csv", old)?;
+        let new = fixture.write("new.csv", new)?;
+        let argv = [&["diff", &old, &new], args].concat();
+        process_csv_diff(&csv_cmd!(Diff, &argv), color)
+    }
+
+    #[test]
+    fn test_diff_patch() -> Result<()> {
+        let patch = diff(
+            "Name,Position,Kit Number\nBuffon,Goalkeeper,1\nPirlo,Midfielder,21\nTotti,Forward,10\n",
+            "Name,Position,Kit Number\nBuffon,Goalkeeper,77\nTotti,Forward,10\nDybala,Forward,10\n",
+            &["--key", "Name", "--format", "json"],
+            false,
+        )?;
+        let patch: Value = serde_json::from_str(&patch)?;
+        assert_eq!(
+            patch,
+            json!([
+                {"op": "test", "path": "/Buffon/Kit Number", "value": "1"},
+                {"op": "replace", "path": "/Buffon/Kit Number", "value": "77"},
+                {"op": "add", "path": "/Dybala", "value": {"Name": "Dybala", "Position": "Forward", "Kit Number": "10"}},
+                {"op": "test", "path": "/Pirlo", "value": {"Name": "Pirlo", "Position": "Midfielder", "Kit Number": "21"}},
+                {"op": "remove", "path": "/Pirlo"},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_table() -> Result<()> {
+        let old = "Name,Kit Number\nBuffon,1\n";
+        let key = ["--key", "Name"];
+        let report = diff(old, "Name,Kit Number\nBuffon,77\n", &key, true)?;
+        assert!(report.starts_with("0 added, 0 removed, 1 changed cells\n"));
+        assert!(report.contains("\x1b[33m~ | Buffon | Kit Number |   1 |  77\x1b[0m"));
+
+        assert_eq!(diff(old, old, &key, false)?, "no differences\n");
+        assert!(diff(old, "Name\nBuffon\nBuffon\n", &key, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_composite_key_and_short_rows() -> Result<()> {
+        let patch = diff(
+            "Team,Name,Kit Number\nJuventus,Buffon/Gigi,1\nRoma,Totti,10\n",
+            "Team,Name,Kit Number\nJuventus,Buffon/Gigi,77\nRoma,Totti,10,\n",
+            &["--key", "Team,Name", "--format", "json"],
+            false,
+        )?;
+        let patch: Value = serde_json::from_str(&patch)?;
+        assert_eq!(
+            patch,
+            json!([
+                {"op": "test", "path": "/Juventus/Buffon~1Gigi/Kit Number", "value": "1"},
+                {"op": "replace", "path": "/Juventus/Buffon~1Gigi/Kit Number", "value": "77"},
+            ])
+        );
+
+        let short = diff(
+            "Name,Position,Kit Number\nBuffon,,\n",
+            "Name,Position,Kit Number\nBuffon\n",
+            &["--key", "Name"],
+            false,
+        )?;
+        assert_eq!(short, "no differences\n");
+        Ok(())
+    }
+}
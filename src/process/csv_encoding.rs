@@ -0,0 +1,90 @@
+use crate::cli::csv::TextEncoding;
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::io::{Cursor, Read};
+
+// enough to see past a long ASCII header row
+const SAMPLE_SIZE: u64 = 64 * 1024;
+const BOM_SIZE: u64 = 3;
+
+/// Transcode the input to UTF-8, a BOM is stripped and wins over `encoding`.
+pub fn decode_reader(mut reader: Box<dyn Read>, encoding: TextEncoding) -> Result<Box<dyn Read>> {
+    let limit = match encoding {
+        TextEncoding::Auto => SAMPLE_SIZE,
+        TextEncoding::Fixed(_) => BOM_SIZE,
+    };
+    let mut sample = Vec::new();
+    (&mut reader).take(limit).read_to_end(&mut sample)?;
+    let (encoding, bom_len) = match (Encoding::for_bom(&sample), encoding) {
+        (Some(found), _) => found,
+        (None, TextEncoding::Fixed(encoding)) => (encoding, 0),
+        (None, TextEncoding::Auto) => (detect_encoding(&sample), 0),
+    };
+    sample.drain(..bom_len);
+    let reader = Cursor::new(sample).chain(reader);
+    if encoding == UTF_8 {
+        // invalid UTF-8 is left to the csv reader to report
+        return Ok(Box::new(reader));
+    }
+    let reader = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_sniffing(false)
+        .build(reader);
+    Ok(Box::new(reader))
+}
+
+/// Guess the encoding from a BOM or the content, preferring UTF-8.
+pub fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    // the sample may end in the middle of a character
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        Err(e) if e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, true);
+    detector.guess(None, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, UTF_16LE};
+
+    fn decode(bytes: Vec<u8>, encoding: TextEncoding) -> Result<String> {
+        let mut out = String::new();
+        decode_reader(Box::new(Cursor::new(bytes)), encoding)?.read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        assert_eq!(detect_encoding(b"Name,Kit Number\n"), UTF_8);
+        assert_eq!(detect_encoding(b"\xff\xfeN\0"), UTF_16LE);
+        // cut inside a multi-byte character
+        assert_eq!(detect_encoding(&"尤文".as_bytes()[..4]), UTF_8);
+        let (gbk, _, _) = GBK.encode("姓名,位置\n布冯,门将\n基耶利尼,后卫\n");
+        assert_eq!(detect_encoding(&gbk), GBK);
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "姓名,位置\n布冯,门将\n";
+        let (gbk, _, _) = GBK.encode(text);
+        assert_eq!(decode(gbk.to_vec(), TextEncoding::Auto)?, text);
+        assert_eq!(decode(gbk.to_vec(), TextEncoding::Fixed(GBK))?, text);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert_eq!(decode(utf16, TextEncoding::Fixed(UTF_8))?, text);
+
+        let bom = [b"\xef\xbb\xbf".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decode(bom, TextEncoding::Auto)?, text);
+        Ok(())
+    }
+}
@@ -0,0 +1,448 @@
+use super::csv_convert::{open_reader, parse_date, parse_date_format, read_headers, read_opts};
+use super::csv_schema::{schema_properties, ColumnSchema, TypeInference};
+use crate::cli::csv::{ColumnType, CsvFakeOpts, TextEncoding};
+use crate::{get_reader, get_writer};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate};
+use csv::WriterBuilder;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Distinct values kept per column of a sample, beyond that it is no enum.
+const MAX_ENUM: usize = 20;
+/// Share of empty cells for nullable schema columns.
+const NULL_RATE: f64 = 0.1;
+/// Dates of schema columns, the format `csv schema` validates first.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const FIRST_NAMES: &[&str] = &[
+    "Alessandro",
+    "Giulia",
+    "Marco",
+    "Sofia",
+    "Luca",
+    "Emma",
+    "Matteo",
+    "Chiara",
+    "Paulo",
+    "Anna",
+    "Federico",
+    "Sara",
+    "Wojciech",
+    "Laura",
+    "Juan",
+    "Elena",
+    "Daniele",
+    "Marta",
+    "Leonardo",
+    "Alice",
+];
+const LAST_NAMES: &[&str] = &[
+    "Rossi", "Bianchi", "Ferrari", "Esposito", "Romano", "Colombo", "Ricci", "Marino", "Greco",
+    "Bruno", "Gallo", "Conti", "De Luca", "Costa", "Fontana", "Moretti", "Silva", "Martin",
+    "Schmidt", "Kowalski",
+];
+const CITIES: &[&str] = &[
+    "Turin",
+    "Milan",
+    "Rome",
+    "Naples",
+    "Florence",
+    "Bologna",
+    "Genoa",
+    "Paris",
+    "Madrid",
+    "Berlin",
+    "Lisbon",
+    "Warsaw",
+    "Amsterdam",
+    "Vienna",
+    "Zurich",
+];
+const COUNTRIES: &[&str] = &[
+    "Italy",
+    "France",
+    "Spain",
+    "Germany",
+    "Portugal",
+    "Poland",
+    "Brazil",
+    "Argentina",
+    "Uruguay",
+    "Netherlands",
+    "Austria",
+    "Switzerland",
+    "Bosnia-Herzegovina",
+    "Colombia",
+];
+const WORDS: &[&str] = &[
+    "alpha", "bravo", "delta", "echo", "gamma", "kilo", "lima", "nova", "omega", "sigma", "tango",
+    "vega", "zulu", "orbit", "pixel", "quartz",
+];
+
+/// How the values of a column are made up.
+#[derive(Debug, Clone, PartialEq)]
+enum Generator {
+    Int(i64, i64),
+    Float(f64, f64),
+    Bool,
+    // the range and the strftime format
+    Date(NaiveDate, NaiveDate, &'static str),
+    Enum(Vec<String>),
+    Text(TextKind),
+}
+
+/// Realistic text, picked from the column name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextKind {
+    FullName,
+    FirstName,
+    LastName,
+    Email,
+    City,
+    Country,
+    Phone,
+    Words,
+}
+
+#[derive(Debug)]
+struct FakeColumn {
+    name: String,
+    generator: Generator,
+    null_rate: f64,
+}
+
+pub fn process_csv_fake(opts: &CsvFakeOpts) -> Result<()> {
+    let columns = match (&opts.schema, &opts.like) {
+        (Some(schema), _) => {
+            let schema: Value = serde_yaml::from_reader(get_reader(schema)?)?;
+            from_schema(&schema)?
+        }
+        (None, Some(like)) => from_sample(like)?,
+        (None, None) => return Err(anyhow!("either --schema or --like is required")),
+    };
+    let mut rng = match opts.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut writer = WriterBuilder::new().from_writer(get_writer(&opts.output)?);
+    writer.write_record(columns.iter().map(|c| &c.name))?;
+    for _ in 0..opts.rows {
+        let row: Vec<String> = columns.iter().map(|c| c.fake(&mut rng)).collect();
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Columns from a schema as written by `csv schema`, `minimum`/`maximum`
+/// bound numbers and `formatMinimum`/`formatMaximum` bound dates.
+fn from_schema(schema: &Value) -> Result<Vec<FakeColumn>> {
+    let mut columns = Vec::new();
+    for (name, prop) in schema_properties(schema)? {
+        let column = ColumnSchema::from_json(name, prop);
+        let number = |bound: &Option<Value>| bound.as_ref().and_then(Value::as_f64);
+        let date = |bound: &Option<Value>, default: NaiveDate| {
+            bound
+                .as_ref()
+                .and_then(Value::as_str)
+                .and_then(parse_date)
+                .unwrap_or(default)
+        };
+        let generator = if let Some(values) = &column.values {
+            Generator::Enum(
+                values
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        v => v.to_string(),
+                    })
+                    .collect(),
+            )
+        } else if column.allows("integer") {
+            let min = number(&column.minimum).map_or(0, |v| v as i64);
+            let max = number(&column.maximum).map_or(min.max(0) + 1000, |v| v as i64);
+            Generator::Int(min, max)
+        } else if column.allows("number") {
+            let min = number(&column.minimum).unwrap_or(0.0);
+            Generator::Float(
+                min,
+                number(&column.maximum).unwrap_or(min.max(0.0) + 1000.0),
+            )
+        } else if column.allows("boolean") {
+            Generator::Bool
+        } else if column.format.as_deref() == Some("date") {
+            Generator::Date(
+                date(
+                    &column.minimum,
+                    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(),
+                ),
+                date(
+                    &column.maximum,
+                    NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+                ),
+                DATE_FORMAT,
+            )
+        } else if column.format.as_deref() == Some("email") {
+            Generator::Text(TextKind::Email)
+        } else {
+            Generator::Text(TextKind::guess(name))
+        };
+        columns.push(FakeColumn {
+            name: name.clone(),
+            generator,
+            null_rate: if column.allows("null") {
+                NULL_RATE
+            } else {
+                0.0
+            },
+        });
+    }
+    Ok(columns)
+}
+
+/// Columns shaped like the sample: same types, ranges, date formats, enums and
+/// share of empty cells. Repeated text values become an enum and are copied
+/// from the sample as they are, any other text is made up.
+fn from_sample(path: &str) -> Result<Vec<FakeColumn>> {
+    let mut reader = open_reader(&read_opts(path, b',', TextEncoding::Auto))?;
+    let headers = read_headers(&mut reader, &[])?;
+    let mut types: Vec<TypeInference> = headers.iter().map(|_| Default::default()).collect();
+    let mut ranges: Vec<Option<(f64, f64)>> = vec![None; headers.len()];
+    let mut dates: Vec<Option<(NaiveDate, NaiveDate, &str)>> = vec![None; headers.len()];
+    let mut distinct: Vec<Option<BTreeSet<String>>> = vec![Some(BTreeSet::new()); headers.len()];
+
+    for record in reader.records() {
+        let record = record?;
+        for (i, field) in record.iter().enumerate().take(headers.len()) {
+            types[i].observe(field);
+            if field.is_empty() {
+                continue;
+            }
+            if let Ok(v) = field.parse::<f64>() {
+                let (min, max) = ranges[i].unwrap_or((v, v));
+                ranges[i] = Some((min.min(v), max.max(v)));
+            }
+            // the first date seen decides the format
+            if let Some((d, format)) = parse_date_format(field) {
+                let (min, max, format) = dates[i].unwrap_or((d, d, format));
+                dates[i] = Some((min.min(d), max.max(d), format));
+            }
+            if let Some(values) = &mut distinct[i] {
+                values.insert(field.to_string());
+                if values.len() > MAX_ENUM {
+                    distinct[i] = None;
+                }
+            }
+        }
+    }
+
+    let mut columns = Vec::with_capacity(headers.len());
+    for (i, name) in headers.iter().enumerate() {
+        let inference = &types[i];
+        let (min, max) = ranges[i].unwrap_or((0.0, 1000.0));
+        let generator = match inference.column_type() {
+            ColumnType::Int => Generator::Int(min as i64, max as i64),
+            ColumnType::Float => Generator::Float(min, max),
+            ColumnType::Bool => Generator::Bool,
+            ColumnType::Date => {
+                let (min, max, format) =
+                    dates[i].unwrap_or((NaiveDate::default(), NaiveDate::default(), DATE_FORMAT));
+                Generator::Date(min, max, format)
+            }
+            // the same rule as csv schema, only repeated values are an enum
+            ColumnType::String => match &distinct[i] {
+                Some(values) if !values.is_empty() && values.len() * 2 <= inference.count() => {
+                    Generator::Enum(values.iter().cloned().collect())
+                }
+                _ => Generator::Text(TextKind::guess(name)),
+            },
+        };
+        let total = inference.count() + inference.nulls();
+        columns.push(FakeColumn {
+            name: name.into(),
+            generator,
+            null_rate: if total == 0 {
+                0.0
+            } else {
+                inference.nulls() as f64 / total as f64
+            },
+        });
+    }
+    Ok(columns)
+}
+
+impl FakeColumn {
+    fn fake(&self, rng: &mut StdRng) -> String {
+        if self.null_rate > 0.0 && rng.gen_bool(self.null_rate.min(1.0)) {
+            return String::new();
+        }
+        match &self.generator {
+            Generator::Int(min, max) => rng.gen_range(*min..=(*max).max(*min)).to_string(),
+            Generator::Float(min, max) if max > min => {
+                format!("{:.2}", rng.gen_range(*min..=*max))
+            }
+            Generator::Float(min, _) => format!("{:.2}", min),
+            Generator::Bool => rng.gen_bool(0.5).to_string(),
+            Generator::Date(min, max, format) => {
+                let days = (*max - *min).num_days().max(0);
+                (*min + Duration::days(rng.gen_range(0..=days)))
+                    .format(format)
+                    .to_string()
+            }
+            Generator::Enum(values) => values.choose(rng).cloned().unwrap_or_default(),
+            Generator::Text(kind) => kind.fake(rng),
+        }
+    }
+}
+
+impl TextKind {
+    fn guess(column: &str) -> Self {
+        let name = column.to_lowercase();
+        let has = |s: &str| name.contains(s);
+        if has("mail") {
+            TextKind::Email
+        } else if has("first") || has("given") {
+            TextKind::FirstName
+        } else if has("last") || has("surname") || has("family") {
+            TextKind::LastName
+        } else if has("name") {
+            TextKind::FullName
+        } else if has("city") || has("town") {
+            TextKind::City
+        } else if has("country") || has("nationality") {
+            TextKind::Country
+        } else if has("phone") || has("mobile") {
+            TextKind::Phone
+        } else {
+            TextKind::Words
+        }
+    }
+
+    fn fake(self, rng: &mut StdRng) -> String {
+        match self {
+            TextKind::FullName => {
+                format!("{} {}", pick(rng, FIRST_NAMES), pick(rng, LAST_NAMES))
+            }
+            TextKind::FirstName => pick(rng, FIRST_NAMES).into(),
+            TextKind::LastName => pick(rng, LAST_NAMES).into(),
+            TextKind::Email => format!(
+                "{}.{}{}@example.com",
+                pick(rng, FIRST_NAMES).to_lowercase(),
+                pick(rng, LAST_NAMES).to_lowercase().replace(' ', ""),
+                rng.gen_range(1..100)
+            ),
+            TextKind::City => pick(rng, CITIES).into(),
+            TextKind::Country => pick(rng, COUNTRIES).into(),
+            TextKind::Phone => format!(
+                "+39 3{:02} {:03} {:04}",
+                rng.gen_range(0..100),
+                rng.gen_range(0..1000),
+                rng.gen_range(0..10000)
+            ),
+            TextKind::Words => {
+                let n = rng.gen_range(2..=4);
+                let words: Vec<&str> = (0..n).map(|_| pick(rng, WORDS)).collect();
+                words.join(" ")
+            }
+        }
+    }
+}
+
+fn pick<'a>(rng: &mut StdRng, list: &[&'a str]) -> &'a str {
+    list.choose(rng).copied().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn fake(fixture: &Fixture, args: &[&str]) -> Result<Vec<csv::StringRecord>> {
+        let output = fixture.path("fake.csv");
+        let argv = [&["fake", "-o", &output], args].concat();
+        process_csv_fake(&csv_cmd!(Fake, &argv))?;
+        let mut reader = csv::Reader::from_path(output)?;
+        let mut rows = vec![reader.headers()?.clone()];
+        for record in reader.records() {
+            rows.push(record?);
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_fake_like_sample() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let args = [
+            "--like",
+            "assets/juventus.csv",
+            "--rows",
+            "50",
+            "--seed",
+            "7",
+        ];
+        let rows = fake(&fixture, &args)?;
+        assert_eq!(rows.len(), 51);
+        assert_eq!(
+            rows[0],
+            vec!["Name", "Position", "DOB", "Nationality", "Kit Number"]
+        );
+        for row in &rows[1..] {
+            assert!(row[0].contains(' '));
+            // the sample writes dates like Apr 18, 1990
+            assert!(NaiveDate::parse_from_str(&row[2], "%b %d, %Y").is_ok());
+            let kit: i64 = row[4].parse()?;
+            assert!((1..=77).contains(&kit));
+        }
+        // a seed makes the output reproducible
+        assert_eq!(rows, fake(&fixture, &args)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_from_schema() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let schema = fixture.write(
+            "schema.yaml",
+            "items:
+  properties:
+    id: {type: integer, minimum: 1, maximum: 5}
+    email: {type: string, format: email}
+    joined: {type: [string, 'null'], format: date, formatMinimum: '2020-01-01', formatMaximum: '2020-01-31'}
+    tier: {type: string, enum: [gold, silver]}
+",
+        )?;
+        let rows = fake(&fixture, &["--schema", &schema, "--rows", "20"])?;
+        assert_eq!(rows[0], vec!["id", "email", "joined", "tier"]);
+        for row in &rows[1..] {
+            assert!((1..=5).contains(&row[0].parse::<i64>()?));
+            assert!(row[1].ends_with("@example.com"));
+            assert!(row[2].is_empty() || row[2].starts_with("2020-01-"));
+            assert!(["gold", "silver"].contains(&&row[3]));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_from_written_schema() -> Result<()> {
+        use crate::process::{process_csv_schema, process_csv_validate};
+
+        let fixture = Fixture::new()?;
+        let schema =
+            process_csv_schema(&csv_cmd!(Schema, &["schema", "-i", "assets/juventus.csv"]))?;
+        let schema = fixture.write("schema.json", &schema.to_string())?;
+        let output = fixture.path("fake.csv");
+        let argv = ["fake", "--schema", &schema, "--rows", "30", "-o", &output];
+        process_csv_fake(&csv_cmd!(Fake, &argv))?;
+        let argv = ["validate", "-i", &output, "--schema", &schema];
+        assert_eq!(
+            process_csv_validate(&csv_cmd!(Validate, &argv))?,
+            Vec::<String>::new()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use super::csv_convert::infer_value;
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// A boolean expression over csv columns, e.g. `Position == "Forward" && "Kit Number" > 10`.
+///
+/// Double quoted names that match a column are column references, otherwise
+/// string literals. Single quotes are always literals, backticks always columns.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Cmp(Operand, CmpOp, Operand),
+    Truthy(Operand),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Operand {
+    Column(usize),
+    Value(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Keeps the selected columns, in the selected order.
+#[derive(Debug)]
+pub struct Projection {
+    indexes: Vec<usize>,
+    headers: StringRecord,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(CmpOp),
+    Ident(String),
+    Quoted(String),
+    Literal(String),
+    Backtick(String),
+    Number(f64),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    headers: &'a StringRecord,
+}
+
+impl Expr {
+    pub fn parse(input: &str, headers: &StringRecord) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            headers,
+        };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(t) => Err(anyhow!("unexpected token {:?} in where expression", t)),
+        }
+    }
+
+    pub fn matches(&self, record: &StringRecord) -> bool {
+        match self {
+            Expr::And(a, b) => a.matches(record) && b.matches(record),
+            Expr::Or(a, b) => a.matches(record) || b.matches(record),
+            Expr::Not(e) => !e.matches(record),
+            Expr::Cmp(a, op, b) => op.test(compare(&a.eval(record), &b.eval(record))),
+            Expr::Truthy(v) => truthy(&v.eval(record)),
+        }
+    }
+}
+
+impl CmpOp {
+    /// Whether the result of `compare` satisfies the operator.
+    pub fn test(self, ord: Option<Ordering>) -> bool {
+        match self {
+            CmpOp::Eq => ord == Some(Ordering::Equal),
+            CmpOp::Ne => ord != Some(Ordering::Equal),
+            CmpOp::Lt => ord == Some(Ordering::Less),
+            CmpOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+            CmpOp::Gt => ord == Some(Ordering::Greater),
+            CmpOp::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+impl Operand {
+    fn eval(&self, record: &StringRecord) -> Value {
+        match self {
+            Operand::Column(i) => infer_value(record.get(*i).unwrap_or_default()),
+            Operand::Value(v) => v.clone(),
+        }
+    }
+}
+
+impl Projection {
+    pub fn try_new(headers: &StringRecord, select: &[String]) -> Result<Self> {
+        let indexes = select
+            .iter()
+            .map(|name| column_index(headers, name))
+            .collect::<Result<Vec<_>>>()?;
+        let headers = indexes.iter().map(|i| &headers[*i]).collect();
+        Ok(Self { indexes, headers })
+    }
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    pub fn apply(&self, record: &StringRecord) -> StringRecord {
+        let mut out: StringRecord = self
+            .indexes
+            .iter()
+            .map(|i| record.get(*i).unwrap_or_default())
+            .collect();
+        out.set_position(record.position().cloned());
+        out
+    }
+}
+
+pub fn column_index(headers: &StringRecord, name: &str) -> Result<usize> {
+    headers
+        .iter()
+        .position(|h| h == name)
+        .ok_or_else(|| anyhow!("unknown column: {}", name))
+}
+
+/// Numbers compare numerically, null only equals null, everything else as text.
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        (a, b) => Some(as_text(a).cmp(&as_text(b))),
+    }
+}
+
+pub fn truthy(v: &Value) -> bool {
+    match v {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+pub fn as_text(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.parse_unary()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(anyhow!("missing ) in where expression")),
+                }
+            }
+            _ => {
+                let left = self.parse_operand()?;
+                match self.peek() {
+                    Some(Token::Op(op)) => {
+                        let op = *op;
+                        self.pos += 1;
+                        Ok(Expr::Cmp(left, op, self.parse_operand()?))
+                    }
+                    _ => Ok(Expr::Truthy(left)),
+                }
+            }
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        let operand = match self.next() {
+            Some(Token::Number(n)) => Operand::Value(n.into()),
+            Some(Token::Literal(s)) => Operand::Value(Value::String(s)),
+            Some(Token::Backtick(name)) => Operand::Column(column_index(self.headers, &name)?),
+            Some(Token::Quoted(s)) => match column_index(self.headers, &s) {
+                Ok(i) => Operand::Column(i),
+                Err(_) => Operand::Value(Value::String(s)),
+            },
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Operand::Value(Value::Bool(true)),
+                "false" => Operand::Value(Value::Bool(false)),
+                "null" => Operand::Value(Value::Null),
+                _ => Operand::Column(column_index(self.headers, &name)?),
+            },
+            t => return Err(anyhow!("expect a column or value, got {:?}", t)),
+        };
+        Ok(operand)
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let token = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('&', Some('&'))
+            | ('|', Some('|'))
+            | ('=', Some('='))
+            | ('!', Some('='))
+            | ('<', Some('='))
+            | ('>', Some('=')) => {
+                i += 2;
+                tokens.push(match c {
+                    '&' => Token::And,
+                    '|' => Token::Or,
+                    '=' => Token::Op(CmpOp::Eq),
+                    '!' => Token::Op(CmpOp::Ne),
+                    '<' => Token::Op(CmpOp::Le),
+                    _ => Token::Op(CmpOp::Ge),
+                });
+                continue;
+            }
+            ('=', _) => Token::Op(CmpOp::Eq),
+            ('<', _) => Token::Op(CmpOp::Lt),
+            ('>', _) => Token::Op(CmpOp::Gt),
+            ('!', _) => Token::Not,
+            ('"' | '\'' | '`', _) => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(anyhow!("unterminated string in where expression")),
+                        Some('\\') if chars.get(i + 1) == Some(&c) => {
+                            s.push(c);
+                            i += 2;
+                        }
+                        Some(ch) if *ch == c => break,
+                        Some(ch) => {
+                            s.push(*ch);
+                            i += 1;
+                        }
+                    }
+                }
+                match c {
+                    '"' => Token::Quoted(s),
+                    '\'' => Token::Literal(s),
+                    _ => Token::Backtick(s),
+                }
+            }
+            (c, n) if c.is_ascii_digit() || (c == '-' && n.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+                let s: String = chars[start..i].iter().collect();
+                let n = s
+                    .parse()
+                    .map_err(|_| anyhow!("invalid number {} in where expression", s))?;
+                tokens.push(Token::Number(n));
+                continue;
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word),
+                });
+                continue;
+            }
+            (c, _) => return Err(anyhow!("unexpected character {} in where expression", c)),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> StringRecord {
+        StringRecord::from(vec!["Name", "Position", "Kit Number"])
+    }
+
+    fn matches(expr: &str, row: Vec<&str>) -> Result<bool> {
+        Ok(Expr::parse(expr, &headers())?.matches(&StringRecord::from(row)))
+    }
+
+    #[test]
+    fn test_where_expression() -> Result<()> {
+        let dybala = vec!["Paulo Dybala", "Forward", "10"];
+        let ronaldo = vec!["Cristiano Ronaldo", "Forward", "7"];
+        let expr = r#"Position == "Forward" && "Kit Number" > 9"#;
+        assert!(matches(expr, dybala.clone())?);
+        assert!(!matches(expr, ronaldo.clone())?);
+        // numeric, not lexical comparison
+        assert!(matches("`Kit Number` < 10", ronaldo.clone())?);
+        assert!(matches(
+            "not (Name = 'Paulo Dybala' or Position != 'Forward')",
+            ronaldo.clone()
+        )?);
+        assert!(matches("Name >= 'P'", dybala.clone())?);
+        assert!(matches("Position", dybala)?);
+        assert!(!matches("Position == null", ronaldo)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_expression_errors() {
+        assert!(Expr::parse("Team == 'Juventus'", &headers()).is_err());
+        assert!(Expr::parse("(Name == 'x'", &headers()).is_err());
+        assert!(Expr::parse("Name == 'x", &headers()).is_err());
+        assert!(Expr::parse("Name == 'x' Position", &headers()).is_err());
+        assert!(Expr::parse("Name ==", &headers()).is_err());
+    }
+
+    #[test]
+    fn test_projection() -> Result<()> {
+        let projection = Projection::try_new(&headers(), &["Kit Number".into(), "Name".into()])?;
+        assert_eq!(projection.headers(), &vec!["Kit Number", "Name"]);
+        let row = StringRecord::from(vec!["Paulo Dybala", "Forward", "10"]);
+        assert_eq!(projection.apply(&row), vec!["10", "Paulo Dybala"]);
+        assert!(Projection::try_new(&headers(), &["Team".into()]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use crate::cli::csv::{ArrayMode, CsvFromOpts, InputFormat};
+use crate::{get_reader, get_writer};
+use anyhow::{bail, Result};
+use csv::{Terminator, WriterBuilder};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Debug)]
+struct Field {
+    key: String,
+    source: String,
+    value: String,
+}
+
+/// Collects flattened records, the header is the union of all keys in first-seen order.
+#[derive(Debug)]
+pub struct Flattener {
+    mode: ArrayMode,
+    separator: String,
+    headers: Vec<String>,
+    // the JSON pointer each column was flattened from, to catch collisions
+    sources: Vec<String>,
+    index: HashMap<String, usize>,
+    rows: Vec<Vec<(usize, String)>>,
+}
+
+pub fn process_csv_from(opts: &CsvFromOpts, format: InputFormat) -> Result<()> {
+    let mut flattener = Flattener::new(opts.arrays, &opts.array_separator);
+    let reader = get_reader(&opts.input)?;
+    match format {
+        InputFormat::Json => flattener.push_document(serde_json::from_reader(reader)?)?,
+        InputFormat::Yaml => flattener.push_document(serde_yaml::from_reader(reader)?)?,
+        InputFormat::Ndjson => {
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    flattener.push(&serde_json::from_str(&line)?)?;
+                }
+            }
+        }
+    }
+
+    let writer = get_writer(&opts.output)?;
+    flattener.write_to(writer, opts.delimiter, opts.excel)
+}
+
+impl Flattener {
+    pub fn new(mode: ArrayMode, separator: &str) -> Self {
+        Self {
+            mode,
+            separator: separator.into(),
+            headers: Vec::new(),
+            sources: Vec::new(),
+            index: HashMap::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// A top level array is a list of records, anything else is a single record.
+    pub fn push_document(&mut self, doc: Value) -> Result<()> {
+        match doc {
+            Value::Array(items) => items.iter().try_for_each(|item| self.push(item)),
+            doc => self.push(&doc),
+        }
+    }
+
+    /// Fails when two different paths flatten to the same column, like
+    /// `{"a.b": 1}` and `{"a": {"b": 2}}`.
+    pub fn push(&mut self, record: &Value) -> Result<()> {
+        let mut fields = Vec::new();
+        match record {
+            Value::Object(_) => self.flatten("", "", record, &mut fields),
+            // scalars and arrays at the top level go into a single `value` column
+            _ => fields.push(Field {
+                key: "value".to_string(),
+                source: String::new(),
+                value: self.encode_array_or_scalar(record),
+            }),
+        }
+        let row = fields
+            .into_iter()
+            .map(|field| Ok((self.column(field.key, field.source)?, field.value)))
+            .collect::<Result<_>>()?;
+        self.rows.push(row);
+        Ok(())
+    }
+
+    /// Records as rows aligned to the headers, missing cells are empty.
+    pub fn rows(&self) -> impl Iterator<Item = Vec<&str>> {
+        self.rows.iter().map(|row| {
+            let mut cells = vec![""; self.headers.len()];
+            for (idx, value) in row {
+                cells[*idx] = value;
+            }
+            cells
+        })
+    }
+
+    /// Excel needs a BOM to detect UTF-8 and prefers CRLF line endings.
+    pub fn write_to(&self, mut writer: impl Write, delimiter: u8, excel: bool) -> Result<()> {
+        let mut builder = WriterBuilder::new();
+        builder.delimiter(delimiter);
+        if excel {
+            writer.write_all(UTF8_BOM)?;
+            builder.terminator(Terminator::CRLF);
+        }
+        let mut writer = builder.from_writer(writer);
+        writer.write_record(&self.headers)?;
+        for row in self.rows() {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn column(&mut self, key: String, source: String) -> Result<usize> {
+        if let Some(idx) = self.index.get(&key) {
+            if self.sources[*idx] != source {
+                bail!(
+                    "column {} is flattened from both {:?} and {:?}",
+                    key,
+                    self.sources[*idx],
+                    source
+                );
+            }
+            return Ok(*idx);
+        }
+        let idx = self.headers.len();
+        self.headers.push(key.clone());
+        self.sources.push(source);
+        self.index.insert(key, idx);
+        Ok(idx)
+    }
+
+    fn flatten(&self, prefix: &str, source: &str, value: &Value, out: &mut Vec<Field>) {
+        match value {
+            // an empty record has no fields, a nested empty object is an empty cell
+            Value::Object(map) if map.is_empty() && source.is_empty() => {}
+            Value::Object(map) if map.is_empty() => out.push(Field {
+                key: prefix.to_string(),
+                source: source.to_string(),
+                value: String::new(),
+            }),
+            Value::Object(map) => {
+                for (k, v) in map {
+                    let key = if prefix.is_empty() {
+                        k.clone()
+                    } else {
+                        format!("{}.{}", prefix, k)
+                    };
+                    let pointer = format!("{}/{}", source, k.replace('~', "~0").replace('/', "~1"));
+                    self.flatten(&key, &pointer, v, out);
+                }
+            }
+            Value::Array(items) if self.mode == ArrayMode::Index && !items.is_empty() => {
+                for (i, v) in items.iter().enumerate() {
+                    let pointer = format!("{}/{}", source, i);
+                    self.flatten(&format!("{}[{}]", prefix, i), &pointer, v, out);
+                }
+            }
+            v => out.push(Field {
+                key: prefix.to_string(),
+                source: source.to_string(),
+                value: self.encode_array_or_scalar(v),
+            }),
+        }
+    }
+
+    fn encode_array_or_scalar(&self, value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            Value::Bool(_) | Value::Number(_) => value.to_string(),
+            Value::Array(items)
+                if self.mode == ArrayMode::Join
+                    && items.iter().all(|v| !v.is_array() && !v.is_object()) =>
+            {
+                items
+                    .iter()
+                    .map(|v| self.encode_array_or_scalar(v))
+                    .collect::<Vec<_>>()
+                    .join(&self.separator)
+            }
+            v => v.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn to_csv(values: Value, mode: ArrayMode) -> Result<String> {
+        let mut flattener = Flattener::new(mode, ";");
+        flattener.push_document(values)?;
+        let mut buf = Vec::new();
+        flattener.write_to(&mut buf, b',', false)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_flatten_nested_objects() -> Result<()> {
+        let data = json!([
+            {"name": "Buffon", "address": {"city": "Turin", "zip": "10100"}},
+            {"name": "Chiellini", "kit": 3, "address": {"city": "Pisa"}, "retired": null}
+        ]);
+        assert_eq!(
+            to_csv(data, ArrayMode::Json)?,
+            "name,address.city,address.zip,kit,retired\nBuffon,Turin,10100,,\nChiellini,Pisa,,3,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_empty_objects() -> Result<()> {
+        let data = json!([{"a": 1}, {}, {"a": 2, "b": {}}]);
+        assert_eq!(to_csv(data, ArrayMode::Json)?, "a,b\n1,\n,\n2,\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_arrays() -> Result<()> {
+        let data = json!({"name": "Buffon", "tags": ["gk", 1]});
+        assert_eq!(
+            to_csv(data.clone(), ArrayMode::Json)?,
+            "name,tags\nBuffon,\"[\"\"gk\"\",1]\"\n"
+        );
+        assert_eq!(
+            to_csv(data.clone(), ArrayMode::Join)?,
+            "name,tags\nBuffon,gk;1\n"
+        );
+        assert_eq!(
+            to_csv(data, ArrayMode::Index)?,
+            "name,tags[0],tags[1]\nBuffon,gk,1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_for_excel() -> Result<()> {
+        let mut flattener = Flattener::new(ArrayMode::Json, ";");
+        flattener.push_document(json!([{"name": "布冯", "kit": 1}]))?;
+        let mut buf = Vec::new();
+        flattener.write_to(&mut buf, b',', true)?;
+        assert_eq!(buf, "\u{feff}name,kit\r\n布冯,1\r\n".as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_yaml_and_scalars() -> Result<()> {
+        let doc: Value = serde_yaml::from_str("- a: 1\n  b: {c: true}\n- 2\n")?;
+        assert_eq!(to_csv(doc, ArrayMode::Json)?, "a,b.c,value\n1,true,\n,,2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatten_collision() {
+        let data = json!([{"a.b": 1}, {"a": {"b": 2}}]);
+        let err = to_csv(data, ArrayMode::Json).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "column a.b is flattened from both \"/a.b\" and \"/a/b\""
+        );
+        let data = json!({"tags[0]": "x", "tags": ["y"]});
+        assert!(to_csv(data, ArrayMode::Index).is_err());
+    }
+}
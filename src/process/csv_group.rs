@@ -0,0 +1,310 @@
+use super::csv_convert::{infer_value, open_reader, read_headers};
+use super::csv_filter::{column_index, compare};
+use super::csv_writer::{OutputTarget, RecordWriter};
+use crate::cli::csv::{AggFunc, Aggregate, CsvGroupOpts};
+use crate::get_writer;
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::BufWriter;
+
+/// Running state of one aggregate within one group.
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    rows: usize,
+    values: usize,
+    numbers: usize,
+    sum: f64,
+    all_int: bool,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+/// Aggregates grouped by the `--by` columns, optionally spread over pivot values.
+#[derive(Debug)]
+struct Groups {
+    keys: Vec<Vec<String>>,
+    index: HashMap<Vec<String>, usize>,
+    pivots: Vec<String>,
+    pivot_index: HashMap<String, usize>,
+    // accumulators[group][pivot][agg]
+    accumulators: Vec<Vec<Option<Vec<Accumulator>>>>,
+}
+
+pub fn process_csv_group(opts: &CsvGroupOpts) -> Result<()> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let by = opts
+        .by
+        .iter()
+        .map(|name| column_index(&headers, name))
+        .collect::<Result<Vec<_>>>()?;
+    let pivot = match &opts.pivot {
+        Some(name) => Some(column_index(&headers, name)?),
+        None => None,
+    };
+    let aggs = opts
+        .agg
+        .iter()
+        .map(|agg| match &agg.column {
+            Some(name) => column_index(&headers, name).map(Some),
+            None if agg.func == AggFunc::Count => Ok(None),
+            None => Err(anyhow!("{} needs a column", agg)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut groups = Groups::new();
+    for record in reader.records() {
+        let record = record?;
+        let key = by
+            .iter()
+            .map(|i| record.get(*i).unwrap_or_default().to_string())
+            .collect();
+        let pivot_value = pivot.map(|i| record.get(i).unwrap_or_default());
+        let accs = groups.entry(key, pivot_value, aggs.len());
+        for (acc, column) in accs.iter_mut().zip(&aggs) {
+            acc.observe(column.map(|i| record.get(i).unwrap_or_default()));
+        }
+    }
+
+    let mut writer = RecordWriter::new(BufWriter::new(get_writer(&opts.output)?), opts.format)
+        .with_target(OutputTarget::new(&opts.format_opts, &opts.read.input));
+    for value in groups.to_values(&opts.by, &opts.agg, pivot.is_some())? {
+        writer.write(&value)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+impl Groups {
+    fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            index: HashMap::new(),
+            pivots: Vec::new(),
+            pivot_index: HashMap::new(),
+            accumulators: Vec::new(),
+        }
+    }
+
+    fn entry(
+        &mut self,
+        key: Vec<String>,
+        pivot: Option<&str>,
+        aggs: usize,
+    ) -> &mut Vec<Accumulator> {
+        let group = match self.index.get(&key) {
+            Some(idx) => *idx,
+            None => {
+                self.index.insert(key.clone(), self.keys.len());
+                self.keys.push(key);
+                self.accumulators.push(vec![None; self.pivots.len().max(1)]);
+                self.keys.len() - 1
+            }
+        };
+        let pivot = match pivot {
+            None => 0,
+            Some(p) => match self.pivot_index.get(p) {
+                Some(idx) => *idx,
+                None => {
+                    self.pivot_index.insert(p.to_string(), self.pivots.len());
+                    self.pivots.push(p.to_string());
+                    self.pivots.len() - 1
+                }
+            },
+        };
+        let cells = &mut self.accumulators[group];
+        if cells.len() <= pivot {
+            cells.resize(pivot + 1, None);
+        }
+        cells[pivot].get_or_insert_with(|| vec![Accumulator::new(); aggs])
+    }
+
+    /// Output column names, pivot values must not clash with each other or the `--by` columns.
+    fn columns(&self, by: &[String], aggs: &[Aggregate], pivoted: bool) -> Result<Vec<String>> {
+        let mut columns = by.to_vec();
+        let mut push = |name: String| {
+            if columns.contains(&name) {
+                bail!(
+                    "output column {:?} appears twice, rename the input column",
+                    name
+                );
+            }
+            columns.push(name);
+            Ok(())
+        };
+        if !pivoted {
+            aggs.iter().try_for_each(|agg| push(agg.to_string()))?;
+        }
+        for pivot in &self.pivots {
+            for agg in aggs {
+                push(match aggs.len() {
+                    1 => pivot.clone(),
+                    _ => format!("{} {}", pivot, agg),
+                })?;
+            }
+        }
+        Ok(columns)
+    }
+
+    fn to_values(&self, by: &[String], aggs: &[Aggregate], pivoted: bool) -> Result<Vec<Value>> {
+        let columns = self.columns(by, aggs, pivoted)?;
+        let values = self
+            .keys
+            .iter()
+            .zip(&self.accumulators)
+            .map(|(key, cells)| {
+                let keys = key.iter().map(|k| Value::String(k.clone()));
+                let results = cells.iter().flat_map(|accs| {
+                    aggs.iter().enumerate().map(move |(j, agg)| {
+                        accs.as_ref().map_or(Value::Null, |a| a[j].result(agg))
+                    })
+                });
+                let results = results.chain(std::iter::repeat(Value::Null));
+                let obj: Map<String, Value> =
+                    columns.iter().cloned().zip(keys.chain(results)).collect();
+                Value::Object(obj)
+            })
+            .collect();
+        Ok(values)
+    }
+}
+
+impl Accumulator {
+    pub fn new() -> Self {
+        Self {
+            rows: 0,
+            values: 0,
+            numbers: 0,
+            sum: 0.0,
+            all_int: true,
+            min: None,
+            max: None,
+        }
+    }
+
+    /// `None` counts the row only, as for `count(*)`.
+    pub fn observe(&mut self, field: Option<&str>) {
+        self.rows += 1;
+        let Some(field) = field else {
+            return;
+        };
+        let value = infer_value(field);
+        if value.is_null() {
+            return;
+        }
+        self.values += 1;
+        if let Some(n) = value.as_f64() {
+            self.numbers += 1;
+            self.sum += n;
+            self.all_int &= value.is_i64();
+        }
+        if self
+            .min
+            .as_ref()
+            .is_none_or(|m| compare(&value, m) == Some(Ordering::Less))
+        {
+            self.min = Some(value.clone());
+        }
+        if self
+            .max
+            .as_ref()
+            .is_none_or(|m| compare(&value, m) == Some(Ordering::Greater))
+        {
+            self.max = Some(value);
+        }
+    }
+
+    pub fn result(&self, agg: &Aggregate) -> Value {
+        match agg.func {
+            AggFunc::Count if agg.column.is_none() => self.rows.into(),
+            AggFunc::Count => self.values.into(),
+            AggFunc::Sum if self.all_int => (self.sum as i64).into(),
+            AggFunc::Sum => self.sum.into(),
+            AggFunc::Avg if self.numbers == 0 => Value::Null,
+            AggFunc::Avg => (self.sum / self.numbers as f64).into(),
+            AggFunc::Min => self.min.clone().unwrap_or(Value::Null),
+            AggFunc::Max => self.max.clone().unwrap_or(Value::Null),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+    use serde_json::json;
+
+    fn group(args: &[&str]) -> Result<Value> {
+        let fixture = Fixture::new()?;
+        let output = fixture.path("group.json");
+        let argv = [&["group", "-i", "assets/juventus.csv", "-o", &output], args].concat();
+        process_csv_group(&csv_cmd!(Group, &argv))?;
+        fixture.read_json("group.json")
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let result = group(&[
+            "--by",
+            "Position",
+            "--agg",
+            "count(*),avg(Kit Number),max(Kit Number)",
+        ])?;
+        assert_eq!(
+            result[0],
+            json!({"Position": "Goalkeeper", "count(*)": 4, "avg(Kit Number)": 36.5, "max(Kit Number)": 77})
+        );
+        assert_eq!(result.as_array().unwrap().len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_pivot() -> Result<()> {
+        let result = group(&[
+            "--by",
+            "Position",
+            "--pivot",
+            "Nationality",
+            "--agg",
+            "sum(Kit Number)",
+            "--format",
+            "json",
+        ])?;
+        let goalkeepers = &result[0];
+        assert_eq!(goalkeepers["Poland"], json!(1));
+        assert_eq!(goalkeepers["Italy"], json!(145));
+        assert_eq!(goalkeepers["Brazil"], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_pivot_collision() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write(
+            "teams.csv",
+            "team,season,goals\nseason,2020,1\nJuventus,2021,2\n",
+        )?;
+        let output = fixture.path("out.json");
+        let argv = [
+            "group",
+            "-i",
+            &input,
+            "-o",
+            &output,
+            "--by",
+            "season",
+            "--pivot",
+            "team",
+            "--agg",
+            "sum(goals)",
+        ];
+        let err = process_csv_group(&csv_cmd!(Group, &argv)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "output column \"season\" appears twice, rename the input column"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use super::csv_convert::{infer_value, open_reader, read_headers, read_opts};
+use super::csv_filter::column_index;
+use super::csv_writer::{OutputTarget, RecordWriter};
+use crate::cli::csv::{CsvJoinOpts, JoinKind};
+use crate::get_writer;
+use anyhow::Result;
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::BufWriter;
+
+/// Builds the joined records, columns are the keys, then the other left and
+/// right columns.
+#[derive(Debug)]
+struct Joiner {
+    keys: Vec<String>,
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+    left: Vec<(usize, String)>,
+    right: Vec<(usize, String)>,
+    infer: bool,
+}
+
+/// Hash join, the right file is loaded into memory and the left one streamed.
+pub fn process_csv_join(opts: &CsvJoinOpts) -> Result<()> {
+    let mut left_reader = open_reader(&read_opts(&opts.left, opts.delimiter, opts.encoding))?;
+    let left_headers = read_headers(&mut left_reader, &[])?;
+    let mut right_reader = open_reader(&read_opts(&opts.right, opts.delimiter, opts.encoding))?;
+    let right_headers = read_headers(&mut right_reader, &[])?;
+    let joiner = Joiner::try_new(opts, &left_headers, &right_headers)?;
+
+    let right_rows = right_reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut index: HashMap<Vec<&str>, Vec<usize>> = HashMap::new();
+    for (i, row) in right_rows.iter().enumerate() {
+        if let Some(key) = key_of(row, &joiner.right_keys) {
+            index.entry(key).or_default().push(i);
+        }
+    }
+    let mut matched = vec![false; right_rows.len()];
+
+    let mut writer = RecordWriter::new(BufWriter::new(get_writer(&opts.output)?), opts.format)
+        .with_target(OutputTarget::new(&opts.format_opts, &opts.left))
+        .with_columns(joiner.columns());
+    for record in left_reader.records() {
+        let record = record?;
+        match key_of(&record, &joiner.left_keys).and_then(|key| index.get(&key)) {
+            Some(rows) => {
+                for i in rows {
+                    matched[*i] = true;
+                    writer.write(&joiner.join(Some(&record), Some(&right_rows[*i])))?;
+                }
+            }
+            None if opts.how != JoinKind::Inner => {
+                writer.write(&joiner.join(Some(&record), None))?;
+            }
+            None => {}
+        }
+    }
+    if opts.how == JoinKind::Full {
+        for (row, _) in right_rows.iter().zip(&matched).filter(|(_, m)| !**m) {
+            writer.write(&joiner.join(None, Some(row)))?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// The join key of a row, `None` when part of it is empty so that rows
+/// missing an id do not all match each other.
+fn key_of<'a>(record: &'a StringRecord, keys: &[usize]) -> Option<Vec<&'a str>> {
+    keys.iter()
+        .map(|i| record.get(*i).filter(|cell| !cell.is_empty()))
+        .collect()
+}
+
+/// `name`, or the first of `name_2`, `name_3`, ... that is not taken yet.
+fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+    if taken.insert(name.clone()) {
+        return name;
+    }
+    let mut n = 2;
+    loop {
+        let candidate = format!("{}_{}", name, n);
+        if taken.insert(candidate.clone()) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+impl Joiner {
+    fn try_new(opts: &CsvJoinOpts, left: &StringRecord, right: &StringRecord) -> Result<Self> {
+        let mut left_keys = Vec::new();
+        let mut right_keys = Vec::new();
+        for (l, r) in &opts.on {
+            left_keys.push(column_index(left, l)?);
+            right_keys.push(column_index(right, r)?);
+        }
+        let keys = opts.on.iter().map(|(l, _)| l.clone()).collect::<Vec<_>>();
+        let rest = |headers: &StringRecord, skip: &[usize]| {
+            headers
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !skip.contains(i))
+                .map(|(i, name)| (i, name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let mut left_rest = rest(left, &left_keys);
+        let mut right_rest = rest(right, &right_keys);
+
+        // only the colliding names get a prefix
+        let left_names: Vec<String> = left_rest.iter().map(|(_, n)| n.clone()).collect();
+        let right_names: Vec<String> = right_rest.iter().map(|(_, n)| n.clone()).collect();
+        let left_renamed: Vec<bool> = left_names.iter().map(|n| right_names.contains(n)).collect();
+        let right_renamed: Vec<bool> = right_names
+            .iter()
+            .map(|n| left_names.contains(n) || keys.contains(n))
+            .collect();
+
+        // a prefixed name may still be taken by another column, it gets a
+        // numeric suffix then, the names of the input are kept as they are
+        let mut taken: HashSet<String> = keys.iter().cloned().collect();
+        for ((_, name), renamed) in left_rest.iter().zip(&left_renamed) {
+            if !renamed {
+                taken.insert(name.clone());
+            }
+        }
+        for ((_, name), renamed) in right_rest.iter().zip(&right_renamed) {
+            if !renamed {
+                taken.insert(name.clone());
+            }
+        }
+        for ((_, name), renamed) in left_rest.iter_mut().zip(&left_renamed) {
+            if *renamed {
+                *name = unique_name(format!("{}{}", opts.left_prefix, name), &mut taken);
+            }
+        }
+        for ((_, name), renamed) in right_rest.iter_mut().zip(&right_renamed) {
+            if *renamed {
+                *name = unique_name(format!("{}{}", opts.right_prefix, name), &mut taken);
+            }
+        }
+
+        Ok(Self {
+            keys,
+            left_keys,
+            right_keys,
+            left: left_rest,
+            right: right_rest,
+            infer: opts.infer,
+        })
+    }
+
+    /// Names of the joined columns, in output order.
+    fn columns(&self) -> Vec<String> {
+        let rest = self.left.iter().chain(&self.right).map(|(_, name)| name);
+        self.keys.iter().chain(rest).cloned().collect()
+    }
+
+    /// Join a left and a right row, the missing side of an outer join is null.
+    fn join(&self, left: Option<&StringRecord>, right: Option<&StringRecord>) -> Value {
+        let mut obj = Map::new();
+        for (i, name) in self.keys.iter().enumerate() {
+            let cell = match (left, right) {
+                (Some(row), _) => row.get(self.left_keys[i]),
+                (None, Some(row)) => row.get(self.right_keys[i]),
+                (None, None) => None,
+            };
+            obj.insert(name.clone(), self.value(cell));
+        }
+        for (columns, row) in [(&self.left, left), (&self.right, right)] {
+            for (i, name) in columns {
+                obj.insert(name.clone(), self.value(row.and_then(|r| r.get(*i))));
+            }
+        }
+        Value::Object(obj)
+    }
+
+    fn value(&self, cell: Option<&str>) -> Value {
+        match cell {
+            None => Value::Null,
+            Some(s) if self.infer => infer_value(s),
+            Some(s) => Value::String(s.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+    use serde_json::json;
+
+    fn join(args: &[&str]) -> Result<Value> {
+        let fixture = Fixture::new()?;
+        let left = fixture.write(
+            "left.csv",
+            "id,name,team\n1,Buffon,Juventus\n2,Pirlo,Juventus\n3,Totti,Roma\n,Trainee,Juventus\n",
+        )?;
+        let right = fixture.write(
+            "right.csv",
+            "player_id,team,caps\n1,Italy,176\n1,Italy U21,11\n4,Italy,3\n,Italy,0\n",
+        )?;
+        let output = fixture.path("join.json");
+        let argv = [
+            &[
+                "join",
+                &left,
+                &right,
+                "--on",
+                "id=player_id",
+                "--infer",
+                "--format",
+                "json",
+                "-o",
+                &output,
+            ],
+            args,
+        ]
+        .concat();
+        process_csv_join(&csv_cmd!(Join, &argv))?;
+        fixture.read_json("join.json")
+    }
+
+    #[test]
+    fn test_inner_join() -> Result<()> {
+        let result = join(&[])?;
+        assert_eq!(
+            result,
+            json!([
+                {"id": 1, "name": "Buffon", "left_team": "Juventus", "right_team": "Italy", "caps": 176},
+                {"id": 1, "name": "Buffon", "left_team": "Juventus", "right_team": "Italy U21", "caps": 11},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_outer_join() -> Result<()> {
+        let result = join(&["--how", "left"])?;
+        assert_eq!(result.as_array().unwrap().len(), 5);
+        assert_eq!(result[3]["name"], json!("Totti"));
+        assert_eq!(result[3]["caps"], Value::Null);
+        // an empty id matches nothing
+        assert_eq!(result[4]["name"], json!("Trainee"));
+        assert_eq!(result[4]["caps"], Value::Null);
+
+        let result = join(&["--how", "full", "--left-prefix", "club_"])?;
+        assert_eq!(result.as_array().unwrap().len(), 7);
+        assert_eq!(
+            result[5],
+            json!({"id": 4, "name": null, "club_team": null, "right_team": "Italy", "caps": 3})
+        );
+        assert_eq!(result[6]["caps"], json!(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefixed_name_collision() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let left = fixture.write("left.csv", "id,x,right_x\n1,a,KEEP\n")?;
+        let right = fixture.write("right.csv", "id,x\n1,b\n")?;
+        let output = fixture.path("join.json");
+        let argv = [
+            "join", &left, &right, "--on", "id", "--format", "json", "-o", &output,
+        ];
+        process_csv_join(&csv_cmd!(Join, &argv))?;
+        assert_eq!(
+            fixture.read_json("join.json")?,
+            json!([{"id": "1", "left_x": "a", "right_x": "KEEP", "right_x_2": "b"}])
+        );
+        Ok(())
+    }
+}
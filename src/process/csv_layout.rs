@@ -0,0 +1,58 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ELLIPSIS: char = '…';
+
+/// Cut the string to the given display width, marking the cut with an ellipsis.
+pub fn truncate(s: &str, max_width: usize) -> String {
+    // newlines would break the table layout
+    let s = s.replace(['\r', '\n'], " ");
+    if s.width() <= max_width {
+        return s;
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+    let mut out = String::new();
+    let mut width = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max_width {
+            break;
+        }
+        width += w;
+        out.push(c);
+    }
+    out.push(ELLIPSIS);
+    out
+}
+
+/// Pad to the given display width, wide CJK characters count as two columns.
+pub fn pad(s: &str, width: usize, right: bool) -> String {
+    let fill = " ".repeat(width.saturating_sub(s.width()));
+    if right {
+        fill + s
+    } else {
+        s.to_string() + &fill
+    }
+}
+
+/// A finite number, right aligned by the table layouts.
+pub fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.parse::<f64>().is_ok_and(|v| v.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_and_pad() {
+        assert_eq!(truncate("Juventus", 10), "Juventus");
+        assert_eq!(truncate("Juventus", 5), "Juve…");
+        assert_eq!(truncate("尤文图斯俱乐部", 7), "尤文图…");
+        assert_eq!(truncate("Juventus", 1), "…");
+        assert_eq!(truncate("Juventus", 0), "");
+        assert_eq!(pad("尤文", 6, false), "尤文  ");
+        assert_eq!(pad("10", 4, true), "  10");
+    }
+}
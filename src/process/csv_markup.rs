@@ -0,0 +1,192 @@
+use super::csv_layout::{is_number, pad};
+use super::csv_writer::{cell_text, OutputTarget};
+use anyhow::Result;
+use serde_json::Value;
+use std::io::Write;
+use unicode_width::UnicodeWidthStr;
+
+const PAGE_STYLE: &str = "table { border-collapse: collapse; font-family: sans-serif; }
+    th, td { border: 1px solid #ccc; padding: 4px 8px; }
+    th { background: #f4f4f4; }";
+
+/// Streams records as an html table, the header comes from the first record.
+#[derive(Debug, Default)]
+pub struct HtmlWriter {
+    columns: Vec<String>,
+}
+
+/// Write a GitHub-flavored table, numeric columns are right aligned.
+pub fn write_markdown(writer: &mut impl Write, records: &[Value]) -> Result<()> {
+    let Some(Value::Object(first)) = records.first() else {
+        return Ok(());
+    };
+    let columns: Vec<&String> = first.keys().collect();
+    let rows: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| {
+            columns
+                .iter()
+                .map(|c| cell_text(&record[c.as_str()]))
+                .collect()
+        })
+        .collect();
+    let numeric: Vec<bool> = (0..columns.len())
+        .map(|i| {
+            let mut cells = rows.iter().map(|row| &row[i]).filter(|c| !c.is_empty());
+            cells.clone().next().is_some() && cells.all(|c| is_number(c))
+        })
+        .collect();
+    let header: Vec<String> = columns.iter().map(|c| escape_markdown(c)).collect();
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| escape_markdown(c)).collect())
+        .collect();
+    // the delimiter row needs at least three dashes
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            rows.iter()
+                .map(|row| row[i].width())
+                .chain([header[i].width(), 3])
+                .max()
+                .unwrap_or(3)
+        })
+        .collect();
+
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    let cells = header.iter().zip(&widths).map(|(h, w)| pad(h, *w, false));
+    writeln!(writer, "{}", line(cells.collect()))?;
+    let rule = widths.iter().zip(&numeric).map(|(w, numeric)| {
+        if *numeric {
+            format!("{}:", "-".repeat(w - 1))
+        } else {
+            "-".repeat(*w)
+        }
+    });
+    writeln!(writer, "{}", line(rule.collect()))?;
+    for row in &rows {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((c, w), numeric)| pad(c, *w, *numeric));
+        writeln!(writer, "{}", line(cells.collect()))?;
+    }
+    Ok(())
+}
+
+impl HtmlWriter {
+    pub fn start(
+        &mut self,
+        writer: &mut impl Write,
+        first: &Value,
+        target: &OutputTarget,
+    ) -> Result<()> {
+        if let Value::Object(obj) = first {
+            self.columns = obj.keys().cloned().collect();
+        }
+        if target.standalone {
+            writeln!(
+                writer,
+                "<!DOCTYPE html>\n<html>\n<head>\n  <meta charset=\"utf-8\">\n  <title>{}</title>\n  <style>\n    {}\n  </style>\n</head>\n<body>",
+                escape_html(&target.title),
+                PAGE_STYLE
+            )?;
+        }
+        writeln!(writer, "<table>\n  <thead>\n    <tr>")?;
+        for column in &self.columns {
+            writeln!(writer, "      <th>{}</th>", escape_html(column))?;
+        }
+        writeln!(writer, "    </tr>\n  </thead>\n  <tbody>")?;
+        Ok(())
+    }
+
+    pub fn row(&mut self, writer: &mut impl Write, value: &Value) -> Result<()> {
+        writeln!(writer, "    <tr>")?;
+        for column in &self.columns {
+            let cell = cell_text(value.get(column).unwrap_or(&Value::Null));
+            let align = if is_number(&cell) {
+                " style=\"text-align: right\""
+            } else {
+                ""
+            };
+            writeln!(writer, "      <td{}>{}</td>", align, escape_html(&cell))?;
+        }
+        writeln!(writer, "    </tr>")?;
+        Ok(())
+    }
+
+    pub fn finish(&mut self, writer: &mut impl Write, target: &OutputTarget) -> Result<()> {
+        writeln!(writer, "  </tbody>\n</table>")?;
+        if target.standalone {
+            writeln!(writer, "</body>\n</html>")?;
+        }
+        Ok(())
+    }
+}
+
+/// GitHub renders inline html in cells, so `<`, `>` and `&` are escaped too.
+fn escape_markdown(s: &str) -> String {
+    escape_html_text(s)
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace(['\r', '\n'], "<br>")
+}
+
+fn escape_html(s: &str) -> String {
+    escape_html_text(s)
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn escape_html_text(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv::OutputFormat;
+    use crate::process::csv_writer::RecordWriter;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_markdown() -> Result<()> {
+        let records = [
+            json!({"Name": "Buffon", "Kit Number": 77, "Note": "a|b <i>&"}),
+            json!({"Name": "尤文", "Kit Number": "1", "Note": null}),
+        ];
+        let mut buf = Vec::new();
+        write_markdown(&mut buf, &records)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "| Name   | Kit Number | Note                |\n\
+             | ------ | ---------: | ------------------- |\n\
+             | Buffon |         77 | a\\|b &lt;i&gt;&amp; |\n\
+             | 尤文   |          1 |                     |\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_html() -> Result<()> {
+        let records = [json!({"Name": "<Buffon & Co>", "Kit Number": 1})];
+        let target = OutputTarget {
+            title: "roster".into(),
+            standalone: true,
+            ..Default::default()
+        };
+        let mut writer = RecordWriter::new(Vec::new(), OutputFormat::Html).with_target(target);
+        for record in &records {
+            writer.write(record)?;
+        }
+        let html = String::from_utf8(writer.finish()?)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>roster</title>"));
+        assert!(html.contains("<td>&lt;Buffon &amp; Co&gt;</td>"));
+        assert!(html.contains("<td style=\"text-align: right\">1</td>"));
+        assert!(html.ends_with("</table>\n</body>\n</html>\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use super::csv_convert::read_headers;
+use crate::cli::csv::CsvReadOpts;
+use anyhow::{anyhow, Result};
+use csv::{ReaderBuilder, StringRecord};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+pub const CHUNK_SIZE: usize = 4 << 20;
+const QUOTE: u8 = b'"';
+
+/// Whole records of the input, with the line and byte they start at.
+#[derive(Debug)]
+pub struct Chunk {
+    pub data: Vec<u8>,
+    pub line: u64,
+    pub byte: u64,
+}
+
+/// Splits csv input into chunks of about `chunk_size` bytes, only ever between
+/// two records. Newlines inside quoted fields do not end a record.
+pub struct Chunker<R> {
+    reader: R,
+    delimiter: u8,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    eof: bool,
+    line: u64,
+    byte: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanState {
+    FieldStart,
+    Unquoted,
+    Quoted,
+    // a quote inside a quoted field, either escaping the next one or closing
+    QuoteInQuoted,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R, delimiter: u8, chunk_size: usize) -> Self {
+        Self {
+            reader,
+            delimiter,
+            chunk_size: chunk_size.max(1),
+            buf: Vec::new(),
+            eof: false,
+            line: 1,
+            byte: 0,
+        }
+    }
+
+    /// Resolve the column names like `read_headers`, the header row is consumed.
+    pub fn read_headers(&mut self, opts: &CsvReadOpts) -> Result<StringRecord> {
+        let end = loop {
+            match scan(&self.buf, self.delimiter, true) {
+                Some(end) => break end,
+                None if self.eof => break self.buf.len(),
+                None => self.fill()?,
+            }
+        };
+        let mut reader = ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(opts.header)
+            .flexible(true)
+            .from_reader(&self.buf[..end]);
+        let headers = read_headers(&mut reader, &opts.names)?;
+        if opts.header {
+            self.take(end);
+        }
+        Ok(headers)
+    }
+
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        loop {
+            if self.eof {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(self.take(self.buf.len())));
+            }
+            if self.buf.len() >= self.chunk_size {
+                if let Some(end) = scan(&self.buf, self.delimiter, false) {
+                    return Ok(Some(self.take(end)));
+                }
+            }
+            self.fill()?;
+        }
+    }
+
+    fn fill(&mut self) -> Result<()> {
+        let n = (&mut self.reader)
+            .take(self.chunk_size as u64)
+            .read_to_end(&mut self.buf)?;
+        self.eof = n < self.chunk_size;
+        Ok(())
+    }
+
+    fn take(&mut self, end: usize) -> Chunk {
+        let rest = self.buf.split_off(end);
+        let data = std::mem::replace(&mut self.buf, rest);
+        let chunk = Chunk {
+            line: self.line,
+            byte: self.byte,
+            data,
+        };
+        self.line += chunk.data.iter().filter(|b| **b == b'\n').count() as u64;
+        self.byte += chunk.data.len() as u64;
+        chunk
+    }
+}
+
+/// The end of the first non-empty record, or of the last record, in `data`.
+/// Follows the csv reader: quotes only open a field at its start, records end
+/// at `\n`, `\r\n` or a bare `\r`. A trailing `\r` waits for the next byte.
+fn scan(data: &[u8], delimiter: u8, first: bool) -> Option<usize> {
+    let mut state = ScanState::FieldStart;
+    let mut empty = true;
+    let mut end = None;
+    for (i, b) in data.iter().enumerate() {
+        state = match (state, *b) {
+            (ScanState::Quoted, QUOTE) => ScanState::QuoteInQuoted,
+            (ScanState::Quoted, _) => ScanState::Quoted,
+            (ScanState::QuoteInQuoted, QUOTE) => ScanState::Quoted,
+            (_, b'\n' | b'\r') if *b == b'\n' || data.get(i + 1).is_some_and(|n| *n != b'\n') => {
+                if !(first && empty) {
+                    end = Some(i + 1);
+                    if first {
+                        return end;
+                    }
+                }
+                empty = true;
+                ScanState::FieldStart
+            }
+            (_, b) if b == delimiter => ScanState::FieldStart,
+            (ScanState::FieldStart, QUOTE) => ScanState::Quoted,
+            (ScanState::FieldStart, b'\r') if empty => ScanState::FieldStart,
+            _ => ScanState::Unquoted,
+        };
+        if state != ScanState::FieldStart || *b == delimiter {
+            empty = false;
+        }
+    }
+    end
+}
+
+/// Maps `work` over the items of `next` on `jobs` threads and hands the
+/// results to `sink` in input order. `next` and `sink` run on the calling
+/// thread, at most two items per thread are in flight.
+pub fn ordered_map<T: Send, R: Send>(
+    jobs: usize,
+    mut next: impl FnMut() -> Result<Option<T>>,
+    work: impl Fn(T) -> Result<R> + Sync,
+    mut sink: impl FnMut(R) -> Result<()>,
+) -> Result<()> {
+    if jobs <= 1 {
+        while let Some(item) = next()? {
+            sink(work(item)?)?;
+        }
+        return Ok(());
+    }
+
+    let (job_tx, job_rx) = mpsc::channel::<(usize, T)>();
+    let (done_tx, done_rx) = mpsc::channel::<(usize, Result<R>)>();
+    let job_rx = Mutex::new(job_rx);
+    let (job_rx, work) = (&job_rx, &work);
+    // the senders move into the scope, so workers stop when it returns early
+    thread::scope(move |s| {
+        for _ in 0..jobs {
+            let done_tx = done_tx.clone();
+            s.spawn(move || {
+                while let Some((seq, item)) = job_rx.lock().ok().and_then(|rx| rx.recv().ok()) {
+                    let result = catch_unwind(AssertUnwindSafe(|| work(item)))
+                        .unwrap_or_else(|_| Err(anyhow!("worker thread panicked")));
+                    if done_tx.send((seq, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(done_tx);
+
+        let mut pending = BTreeMap::new();
+        let (mut sent, mut written, mut eof) = (0, 0, false);
+        loop {
+            while !eof && sent - written < jobs * 2 {
+                match next()? {
+                    Some(item) => {
+                        job_tx
+                            .send((sent, item))
+                            .map_err(|_| anyhow!("worker threads stopped"))?;
+                        sent += 1;
+                    }
+                    None => eof = true,
+                }
+            }
+            if written == sent {
+                return Ok(());
+            }
+            let (seq, result) = done_rx
+                .recv()
+                .map_err(|_| anyhow!("worker threads stopped"))?;
+            pending.insert(seq, result);
+            while let Some(result) = pending.remove(&written) {
+                sink(result?)?;
+                written += 1;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::csv::TextEncoding;
+    use crate::process::csv_convert::read_opts;
+
+    #[test]
+    fn test_chunker_splits_between_records() -> Result<()> {
+        let input = "Name,Note\nBuffon,\"one\ntwo\"\nPirlo,\"say \"\"hi\"\"\n\"\n\nTotti,x\n";
+        let mut chunker = Chunker::new(input.as_bytes(), b',', 8);
+        let headers = chunker.read_headers(&read_opts("-", b',', TextEncoding::Auto))?;
+        assert_eq!(headers, vec!["Name", "Note"]);
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = chunker.next_chunk()? {
+            chunks.push(chunk);
+        }
+        let data: Vec<&str> = chunks
+            .iter()
+            .map(|c| std::str::from_utf8(&c.data).unwrap())
+            .collect();
+        assert_eq!(
+            data,
+            [
+                "Buffon,\"one\ntwo\"\n",
+                "Pirlo,\"say \"\"hi\"\"\n\"\n\n",
+                "Totti,x\n"
+            ]
+        );
+        assert_eq!(
+            chunks.iter().map(|c| (c.line, c.byte)).collect::<Vec<_>>(),
+            [(2, 10), (4, 27), (7, 48)]
+        );
+
+        // old Mac files end records with a bare \r
+        let mut chunker = Chunker::new("Name\rBuffon\r\"a\rb\"\r\nTotti\r".as_bytes(), b',', 1);
+        let headers = chunker.read_headers(&read_opts("-", b',', TextEncoding::Auto))?;
+        assert_eq!(headers, vec!["Name"]);
+        let mut data = Vec::new();
+        while let Some(chunk) = chunker.next_chunk()? {
+            data.push(String::from_utf8(chunk.data)?);
+        }
+        assert_eq!(data, ["Buffon\r", "\"a\rb\"\r\n", "Totti\r"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ordered_map_keeps_order() -> Result<()> {
+        let mut items = 0..100u64;
+        let mut out = Vec::new();
+        ordered_map(
+            4,
+            || Ok(items.next()),
+            |i| {
+                // later items finish first
+                thread::sleep(std::time::Duration::from_micros((100 - i) * 10));
+                Ok(i * 2)
+            },
+            |r| {
+                out.push(r);
+                Ok(())
+            },
+        )?;
+        assert_eq!(out, (0..100).map(|i| i * 2).collect::<Vec<_>>());
+
+        let mut items = 0..100u64;
+        let result = ordered_map(
+            4,
+            || Ok(items.next()),
+            |i| match i {
+                50 => Err(anyhow!("bad item")),
+                i => Ok(i),
+            },
+            |_| Ok(()),
+        );
+        assert_eq!(result.unwrap_err().to_string(), "bad item");
+        Ok(())
+    }
+}
@@ -0,0 +1,1248 @@
+use super::csv_convert::{infer_value, open_reader, read_headers, read_opts};
+use super::csv_filter::{as_text, compare, truthy, CmpOp};
+use super::csv_group::Accumulator;
+use super::csv_writer::{OutputTarget, RecordWriter};
+use crate::cli::csv::{AggFunc, Aggregate, CsvQueryOpts};
+use crate::get_writer;
+use anyhow::{anyhow, bail, Result};
+use csv::StringRecord;
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::BufWriter;
+use std::path::Path;
+
+const RESERVED: &[&str] = &[
+    "select", "distinct", "from", "where", "group", "by", "order", "asc", "desc", "limit",
+    "offset", "join", "inner", "left", "outer", "on", "as", "and", "or", "not", "is", "null",
+    "like", "in",
+];
+
+/// A `SELECT` statement of the supported subset:
+///
+/// ```sql
+/// SELECT [DISTINCT] items FROM table [alias]
+///   [[INNER | LEFT [OUTER]] JOIN table [alias] ON a = b [AND ...]]...
+///   [WHERE cond] [GROUP BY exprs] [ORDER BY item [ASC | DESC], ...]
+///   [LIMIT n] [OFFSET n]
+/// ```
+#[derive(Debug, PartialEq)]
+struct Query {
+    distinct: bool,
+    items: Vec<SelectItem>,
+    from: TableRef,
+    joins: Vec<Join>,
+    filter: Option<Expr>,
+    group_by: Vec<Expr>,
+    // (key, descending)
+    order_by: Vec<(OrderKey, bool)>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum SelectItem {
+    Wildcard(Option<String>),
+    Expr(Expr, Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+struct TableRef {
+    path: String,
+    alias: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Join {
+    table: TableRef,
+    left: bool,
+    on: Expr,
+}
+
+#[derive(Debug, PartialEq)]
+enum OrderKey {
+    Position(usize),
+    Expr(Expr),
+}
+
+/// Column names are resolved by `bind`, afterwards they are `Column(table, index)`.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Name(Option<String>, String),
+    Column(usize, usize),
+    Value(Value),
+    Agg(AggFunc, Option<Box<Expr>>),
+    Cmp(Box<Expr>, CmpOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    IsNull(Box<Expr>),
+    Like(Box<Expr>, String),
+    In(Box<Expr>, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Ident(String),
+    Str(String),
+    Number(Number),
+    Symbol(&'static str),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+#[derive(Debug)]
+struct Table {
+    alias: String,
+    headers: StringRecord,
+    rows: Vec<StringRecord>,
+}
+
+/// A joined row, the row index in every table, `None` for the missing side
+/// of a left join.
+type Row = Vec<Option<usize>>;
+
+/// Loads every file into memory, joins are hash joins on the `ON` equalities.
+pub fn process_csv_query(opts: &CsvQueryOpts) -> Result<()> {
+    let mut query = Query::parse(&opts.sql)?;
+    let mut tables = vec![load_table(&query.from, opts)?];
+    for join in &query.joins {
+        tables.push(load_table(&join.table, opts)?);
+    }
+    query.bind(&tables)?;
+
+    let mut writer = RecordWriter::new(BufWriter::new(get_writer(&opts.output)?), opts.format)
+        .with_target(OutputTarget::new(&opts.format_opts, &query.from.path));
+    for value in query.run(&tables)? {
+        writer.write(&value)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn load_table(table: &TableRef, opts: &CsvQueryOpts) -> Result<Table> {
+    // a bare name may leave out the extension
+    let mut path = table.path.clone();
+    if !Path::new(&path).exists() && Path::new(&format!("{}.csv", path)).exists() {
+        path += ".csv";
+    }
+    let mut reader = open_reader(&read_opts(&path, opts.delimiter, opts.encoding))?;
+    let headers = read_headers(&mut reader, &[])?;
+    let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let alias = match &table.alias {
+        Some(alias) => alias.clone(),
+        None => Path::new(&path)
+            .file_stem()
+            .map_or(path.clone(), |s| s.to_string_lossy().into_owned()),
+    };
+    Ok(Table {
+        alias,
+        headers,
+        rows,
+    })
+}
+
+impl Query {
+    fn parse(sql: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(sql)?,
+            pos: 0,
+        };
+        let query = parser.parse_query()?;
+        parser.symbol(";");
+        match parser.peek() {
+            None => Ok(query),
+            Some(t) => Err(anyhow!("unexpected {:?} at the end of the query", t)),
+        }
+    }
+
+    /// Resolve column names against the loaded tables. Order keys naming an
+    /// output column become its position, group keys that are a position
+    /// become the expression of that output column.
+    fn bind(&mut self, tables: &[Table]) -> Result<()> {
+        let aliases: Vec<Option<&String>> = self
+            .items
+            .iter()
+            .map(|item| match item {
+                SelectItem::Expr(_, alias) => alias.as_ref(),
+                SelectItem::Wildcard(_) => None,
+            })
+            .collect();
+        for (key, _) in self.order_by.iter_mut() {
+            if let OrderKey::Expr(Expr::Name(None, name)) = key {
+                if let Some(i) = aliases.iter().position(|a| a.is_some_and(|a| a == name)) {
+                    *key = OrderKey::Position(i + 1);
+                }
+            }
+        }
+        for item in self.items.iter_mut() {
+            match item {
+                SelectItem::Expr(expr, _) => expr.bind(tables)?,
+                SelectItem::Wildcard(Some(alias)) => {
+                    if !tables.iter().any(|t| t.alias == *alias) {
+                        bail!("unknown table: {}", alias);
+                    }
+                }
+                SelectItem::Wildcard(None) => {}
+            }
+        }
+        for (i, join) in self.joins.iter_mut().enumerate() {
+            join.on.bind(&tables[..i + 2])?;
+            if join.on.has_aggregate() {
+                bail!("aggregates are not allowed in JOIN ... ON");
+            }
+        }
+        if let Some(filter) = &mut self.filter {
+            filter.bind(tables)?;
+            if filter.has_aggregate() {
+                bail!("aggregates are not allowed in WHERE");
+            }
+        }
+        let columns = self.columns(tables);
+        for expr in self.group_by.iter_mut() {
+            if let Expr::Value(Value::Number(n)) = expr {
+                let position = n.as_u64().unwrap_or_default() as usize;
+                *expr = match position.checked_sub(1).and_then(|i| columns.get(i)) {
+                    Some((Expr::Agg(..), _)) => bail!("GROUP BY {} refers to an aggregate", n),
+                    Some((column, _)) => column.clone(),
+                    None => bail!("GROUP BY {} is out of range", n),
+                };
+            }
+            expr.bind(tables)?;
+        }
+        for (key, _) in self.order_by.iter_mut() {
+            if let OrderKey::Expr(expr) = key {
+                expr.bind(tables)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run(&self, tables: &[Table]) -> Result<Vec<Value>> {
+        let mut rows: Vec<Row> = (0..tables[0].rows.len()).map(|i| vec![Some(i)]).collect();
+        for (i, join) in self.joins.iter().enumerate() {
+            rows = join.apply(rows, tables, i + 1)?;
+        }
+        if let Some(filter) = &self.filter {
+            rows.retain(|row| truthy(&filter.eval(row, tables)));
+        }
+
+        let columns = self.columns(tables);
+        let names = output_names(&columns, tables);
+        let grouped =
+            !self.group_by.is_empty() || columns.iter().any(|(e, _)| matches!(e, Expr::Agg(..)));
+        // output values with the source row, order keys may need the latter
+        let mut results: Vec<(Vec<Value>, Row)> = if grouped {
+            self.aggregate(&rows, &columns, tables)?
+        } else {
+            rows.into_iter()
+                .map(|row| {
+                    let values = columns.iter().map(|(e, _)| e.eval(&row, tables)).collect();
+                    (values, row)
+                })
+                .collect()
+        };
+
+        if self.distinct {
+            let mut seen = HashSet::new();
+            results.retain(|(values, _)| seen.insert(Value::from(values.clone()).to_string()));
+        }
+        if !self.order_by.is_empty() {
+            let mut keyed = Vec::with_capacity(results.len());
+            for (values, row) in results {
+                let keys = self
+                    .order_by
+                    .iter()
+                    .map(|(key, _)| match key {
+                        OrderKey::Position(i) => values
+                            .get(i - 1)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("ORDER BY {} is out of range", i)),
+                        OrderKey::Expr(expr) => match columns.iter().position(|(e, _)| e == expr) {
+                            Some(i) => Ok(values[i].clone()),
+                            None if expr.has_aggregate() => {
+                                Err(anyhow!("an aggregate in ORDER BY must also be selected"))
+                            }
+                            None => Ok(expr.eval(&row, tables)),
+                        },
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                keyed.push((keys, values, row));
+            }
+            keyed.sort_by(|a, b| {
+                for (i, (_, descending)) in self.order_by.iter().enumerate() {
+                    let ord = order_values(&a.0[i], &b.0[i]);
+                    let ord = if *descending { ord.reverse() } else { ord };
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                }
+                Ordering::Equal
+            });
+            results = keyed.into_iter().map(|(_, v, r)| (v, r)).collect();
+        }
+
+        Ok(results
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(values, _)| Value::Object(names.iter().cloned().zip(values).collect()))
+            .collect())
+    }
+
+    /// The output columns with wildcards expanded, and their names.
+    fn columns(&self, tables: &[Table]) -> Vec<(Expr, String)> {
+        let mut columns = Vec::new();
+        for item in &self.items {
+            match item {
+                SelectItem::Wildcard(alias) => {
+                    for (t, table) in tables.iter().enumerate() {
+                        if alias.as_ref().is_none_or(|a| *a == table.alias) {
+                            for (i, name) in table.headers.iter().enumerate() {
+                                columns.push((Expr::Column(t, i), name.to_string()));
+                            }
+                        }
+                    }
+                }
+                SelectItem::Expr(expr, alias) => {
+                    let name = match (alias, expr) {
+                        (Some(alias), _) => alias.clone(),
+                        (None, Expr::Column(t, i)) => tables[*t].headers[*i].to_string(),
+                        (None, Expr::Agg(..)) => expr.aggregate(tables).to_string(),
+                        (None, Expr::Value(v)) => as_text(v),
+                        (None, _) => format!("column{}", columns.len() + 1),
+                    };
+                    columns.push((expr.clone(), name));
+                }
+            }
+        }
+        columns
+    }
+
+    /// One result per group, the other columns take the first row of the group.
+    fn aggregate(
+        &self,
+        rows: &[Row],
+        columns: &[(Expr, String)],
+        tables: &[Table],
+    ) -> Result<Vec<(Vec<Value>, Row)>> {
+        for expr in columns.iter().map(|(e, _)| e).chain(&self.group_by) {
+            if !matches!(expr, Expr::Agg(..)) && expr.has_aggregate() {
+                bail!("aggregates can not be nested in expressions");
+            }
+        }
+        let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut groups: Vec<(Row, Vec<Accumulator>)> = Vec::new();
+        for row in rows {
+            let key = self
+                .group_by
+                .iter()
+                .map(|e| e.eval(row, tables).to_string())
+                .collect();
+            let group = *index.entry(key).or_insert_with(|| {
+                groups.push((row.clone(), vec![Accumulator::new(); columns.len()]));
+                groups.len() - 1
+            });
+            for ((expr, _), acc) in columns.iter().zip(groups[group].1.iter_mut()) {
+                match expr {
+                    Expr::Agg(_, None) => acc.observe(None),
+                    // the missing side of a left join is NULL, the aggregate skips it
+                    Expr::Agg(_, Some(arg)) => {
+                        if let Expr::Column(t, i) = arg.as_ref() {
+                            if let Some(cell) = cell(row, tables, *t, *i) {
+                                acc.observe(Some(cell));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        // aggregates without GROUP BY make a single group, even with no rows
+        if groups.is_empty() && self.group_by.is_empty() {
+            groups.push((
+                vec![None; tables.len()],
+                vec![Accumulator::new(); columns.len()],
+            ));
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(row, accs)| {
+                let values = columns
+                    .iter()
+                    .zip(&accs)
+                    .map(|((expr, _), acc)| match expr {
+                        Expr::Agg(..) => acc.result(&expr.aggregate(tables)),
+                        _ => expr.eval(&row, tables),
+                    })
+                    .collect();
+                (values, row)
+            })
+            .collect())
+    }
+}
+
+impl Join {
+    /// Hash join of the rows so far with the table at `t`. Keys compare like
+    /// `WHERE a = b`, empty cells are NULL and match nothing.
+    fn apply(&self, rows: Vec<Row>, tables: &[Table], t: usize) -> Result<Vec<Row>> {
+        let mut pairs = Vec::new();
+        collect_equalities(&self.on, t, &mut pairs)?;
+        let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+        for (i, record) in tables[t].rows.iter().enumerate() {
+            let key: Option<Vec<String>> = pairs
+                .iter()
+                .map(|(_, c)| join_key(record.get(*c)))
+                .collect();
+            if let Some(key) = key {
+                index.entry(key).or_default().push(i);
+            }
+        }
+
+        let mut joined = Vec::new();
+        for row in rows {
+            let key: Option<Vec<String>> = pairs
+                .iter()
+                .map(|((lt, lc), _)| join_key(cell(&row, tables, *lt, *lc)))
+                .collect();
+            match key.and_then(|key| index.get(&key)) {
+                Some(matches) => {
+                    for i in matches {
+                        let mut row = row.clone();
+                        row.push(Some(*i));
+                        joined.push(row);
+                    }
+                }
+                None if self.left => {
+                    let mut row = row;
+                    row.push(None);
+                    joined.push(row);
+                }
+                None => {}
+            }
+        }
+        Ok(joined)
+    }
+}
+
+/// Split `ON a = b AND c = d` into pairs of (earlier table column, column of `t`).
+fn collect_equalities(
+    expr: &Expr,
+    t: usize,
+    pairs: &mut Vec<((usize, usize), usize)>,
+) -> Result<()> {
+    match expr {
+        Expr::And(a, b) => {
+            collect_equalities(a, t, pairs)?;
+            collect_equalities(b, t, pairs)
+        }
+        Expr::Cmp(a, CmpOp::Eq, b) => match (a.as_ref(), b.as_ref()) {
+            (Expr::Column(lt, lc), Expr::Column(rt, rc)) if *lt < t && *rt == t => {
+                pairs.push(((*lt, *lc), *rc));
+                Ok(())
+            }
+            (Expr::Column(rt, rc), Expr::Column(lt, lc)) if *lt < t && *rt == t => {
+                pairs.push(((*lt, *lc), *rc));
+                Ok(())
+            }
+            _ => Err(anyhow!(
+                "JOIN ... ON must compare a column of the joined table to an earlier one"
+            )),
+        },
+        _ => Err(anyhow!(
+            "JOIN ... ON only supports equalities joined by AND"
+        )),
+    }
+}
+
+/// A cell as a hash key, numbers by value so `1` joins `1.0`, `None` for NULL.
+fn join_key(cell: Option<&str>) -> Option<String> {
+    match infer_value(cell?) {
+        Value::Null => None,
+        Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+            (Some(i), _) => Some(i.to_string()),
+            (None, Some(f)) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                Some((f as i64).to_string())
+            }
+            _ => Some(n.to_string()),
+        },
+        v => Some(as_text(&v)),
+    }
+}
+
+fn cell<'a>(row: &[Option<usize>], tables: &'a [Table], t: usize, i: usize) -> Option<&'a str> {
+    row.get(t)
+        .copied()
+        .flatten()
+        .and_then(|r| tables[t].rows[r].get(i))
+}
+
+/// Output names are unique, colliding columns are prefixed with their table.
+fn output_names(columns: &[(Expr, String)], tables: &[Table]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|(expr, name)| {
+            let collides = columns.iter().filter(|(_, n)| n == name).count() > 1;
+            match expr {
+                Expr::Column(t, _) if collides => format!("{}.{}", tables[*t].alias, name),
+                _ => name.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Like `compare`, with nulls first so the order is total.
+fn order_values(a: &Value, b: &Value) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        _ => compare(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// SQL `LIKE`, `%` matches any run of characters and `_` a single one.
+fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let (mut t, mut p) = (0, 0);
+    // where to resume after the last %
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some('_') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+impl Expr {
+    fn bind(&mut self, tables: &[Table]) -> Result<()> {
+        match self {
+            Expr::Name(table, name) => {
+                let mut found = Vec::new();
+                for (t, candidate) in tables.iter().enumerate() {
+                    if table.as_ref().is_some_and(|a| *a != candidate.alias) {
+                        continue;
+                    }
+                    if let Some(i) = candidate.headers.iter().position(|h| h == name) {
+                        found.push((t, i));
+                    }
+                }
+                let full_name = match table {
+                    Some(table) => format!("{}.{}", table, name),
+                    None => name.clone(),
+                };
+                *self = match found[..] {
+                    [(t, i)] => Expr::Column(t, i),
+                    [] => bail!("unknown column: {}", full_name),
+                    _ => bail!("ambiguous column: {}", full_name),
+                };
+            }
+            Expr::Agg(_, Some(arg)) => {
+                arg.bind(tables)?;
+                if !matches!(arg.as_ref(), Expr::Column(..)) {
+                    bail!("aggregates only take a column");
+                }
+            }
+            Expr::Column(..) | Expr::Value(_) | Expr::Agg(_, None) => {}
+            Expr::Cmp(a, _, b) | Expr::And(a, b) | Expr::Or(a, b) => {
+                a.bind(tables)?;
+                b.bind(tables)?;
+            }
+            Expr::Not(e) | Expr::IsNull(e) | Expr::Like(e, _) => e.bind(tables)?,
+            Expr::In(e, list) => {
+                e.bind(tables)?;
+                for item in list {
+                    item.bind(tables)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&self, row: &[Option<usize>], tables: &[Table]) -> Value {
+        match self {
+            Expr::Column(t, i) => cell(row, tables, *t, *i).map_or(Value::Null, infer_value),
+            Expr::Value(v) => v.clone(),
+            // unbound names do not survive `bind`, aggregates are computed per
+            // group and rejected by `bind` anywhere else
+            Expr::Name(..) | Expr::Agg(..) => Value::Null,
+            Expr::Cmp(a, op, b) => {
+                Value::Bool(op.test(compare(&a.eval(row, tables), &b.eval(row, tables))))
+            }
+            Expr::And(a, b) => {
+                Value::Bool(truthy(&a.eval(row, tables)) && truthy(&b.eval(row, tables)))
+            }
+            Expr::Or(a, b) => {
+                Value::Bool(truthy(&a.eval(row, tables)) || truthy(&b.eval(row, tables)))
+            }
+            Expr::Not(e) => Value::Bool(!truthy(&e.eval(row, tables))),
+            Expr::IsNull(e) => Value::Bool(e.eval(row, tables).is_null()),
+            Expr::Like(e, pattern) => match e.eval(row, tables) {
+                Value::Null => Value::Bool(false),
+                v => Value::Bool(like(&as_text(&v), pattern)),
+            },
+            Expr::In(e, list) => {
+                let v = e.eval(row, tables);
+                Value::Bool(
+                    list.iter()
+                        .any(|item| compare(&v, &item.eval(row, tables)) == Some(Ordering::Equal)),
+                )
+            }
+        }
+    }
+
+    fn has_aggregate(&self) -> bool {
+        match self {
+            Expr::Agg(..) => true,
+            Expr::Cmp(a, _, b) | Expr::And(a, b) | Expr::Or(a, b) => {
+                a.has_aggregate() || b.has_aggregate()
+            }
+            Expr::Not(e) | Expr::IsNull(e) | Expr::Like(e, _) => e.has_aggregate(),
+            Expr::In(e, list) => e.has_aggregate() || list.iter().any(|i| i.has_aggregate()),
+            Expr::Name(..) | Expr::Column(..) | Expr::Value(_) => false,
+        }
+    }
+
+    /// The aggregate of an `Agg`, named like `csv group` names its columns.
+    fn aggregate(&self, tables: &[Table]) -> Aggregate {
+        match self {
+            Expr::Agg(func, arg) => Aggregate {
+                func: *func,
+                column: arg.as_deref().map(|arg| match arg {
+                    Expr::Column(t, i) => tables[*t].headers[*i].to_string(),
+                    Expr::Name(_, name) => name.clone(),
+                    _ => String::new(),
+                }),
+            },
+            _ => Aggregate {
+                func: AggFunc::Count,
+                column: None,
+            },
+        }
+    }
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consume the keyword if it is next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "expect {}, got {:?}",
+                keyword.to_uppercase(),
+                self.peek()
+            ))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(anyhow!("expect {}, got {:?}", symbol, self.peek()))
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query> {
+        self.expect_keyword("select")?;
+        let distinct = self.keyword("distinct");
+        let mut items = vec![self.parse_select_item()?];
+        while self.symbol(",") {
+            items.push(self.parse_select_item()?);
+        }
+        self.expect_keyword("from")?;
+        let from = self.parse_table()?;
+
+        let mut joins = Vec::new();
+        loop {
+            let left = if self.keyword("left") {
+                self.keyword("outer");
+                true
+            } else {
+                self.keyword("inner");
+                false
+            };
+            if !self.keyword("join") {
+                if left {
+                    bail!("expect JOIN after LEFT, got {:?}", self.peek());
+                }
+                break;
+            }
+            let table = self.parse_table()?;
+            self.expect_keyword("on")?;
+            let on = self.parse_or()?;
+            joins.push(Join { table, left, on });
+        }
+
+        let filter = match self.keyword("where") {
+            true => Some(self.parse_or()?),
+            false => None,
+        };
+        let mut group_by = Vec::new();
+        if self.keyword("group") {
+            self.expect_keyword("by")?;
+            group_by.push(self.parse_or()?);
+            while self.symbol(",") {
+                group_by.push(self.parse_or()?);
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let key = match self.peek() {
+                    Some(Token::Number(n)) if n.as_u64().is_some_and(|n| n > 0) => {
+                        let n = n.as_u64().unwrap_or_default() as usize;
+                        self.pos += 1;
+                        OrderKey::Position(n)
+                    }
+                    _ => OrderKey::Expr(self.parse_or()?),
+                };
+                let descending = self.keyword("desc");
+                if !descending {
+                    self.keyword("asc");
+                }
+                order_by.push((key, descending));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = match self.keyword("limit") {
+            true => Some(self.parse_count("LIMIT")?),
+            false => None,
+        };
+        let offset = match self.keyword("offset") {
+            true => self.parse_count("OFFSET")?,
+            false => 0,
+        };
+        Ok(Query {
+            distinct,
+            items,
+            from,
+            joins,
+            filter,
+            group_by,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_select_item(&mut self) -> Result<SelectItem> {
+        if self.symbol("*") {
+            return Ok(SelectItem::Wildcard(None));
+        }
+        // alias.*
+        if let (
+            Some(Token::Word(t) | Token::Ident(t)),
+            Some(Token::Symbol(".")),
+            Some(Token::Symbol("*")),
+        ) = (
+            self.tokens.get(self.pos),
+            self.tokens.get(self.pos + 1),
+            self.tokens.get(self.pos + 2),
+        ) {
+            let table = t.clone();
+            self.pos += 3;
+            return Ok(SelectItem::Wildcard(Some(table)));
+        }
+        let expr = self.parse_or()?;
+        Ok(SelectItem::Expr(expr, self.parse_alias()?))
+    }
+
+    fn parse_alias(&mut self) -> Result<Option<String>> {
+        let explicit = self.keyword("as");
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(Some(name))
+            }
+            Some(Token::Word(name)) if !RESERVED.contains(&name.to_ascii_lowercase().as_str()) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(Some(name))
+            }
+            t if explicit => Err(anyhow!("expect a name after AS, got {:?}", t)),
+            _ => Ok(None),
+        }
+    }
+
+    /// A quoted file name, or a bare one like `juventus.csv`.
+    fn parse_table(&mut self) -> Result<TableRef> {
+        let path = match self.next() {
+            Some(Token::Str(path) | Token::Ident(path)) => path,
+            Some(Token::Word(word)) => {
+                let mut path = word;
+                while let (Some(Token::Symbol(".")), Some(Token::Word(ext))) =
+                    (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+                {
+                    path = format!("{}.{}", path, ext);
+                    self.pos += 2;
+                }
+                path
+            }
+            t => bail!("expect a file name, got {:?}", t),
+        };
+        Ok(TableRef {
+            path,
+            alias: self.parse_alias()?,
+        })
+    }
+
+    fn parse_count(&mut self, clause: &str) -> Result<usize> {
+        match self.next() {
+            Some(Token::Number(n)) if n.is_u64() => Ok(n.as_u64().unwrap_or_default() as usize),
+            t => Err(anyhow!("expect a count after {}, got {:?}", clause, t)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut left = self.parse_and()?;
+        while self.keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut left = self.parse_not()?;
+        while self.keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<Expr> {
+        let left = self.parse_primary()?;
+        if let Some(Token::Symbol(s)) = self.peek() {
+            let op = match *s {
+                "=" => Some(CmpOp::Eq),
+                "<>" | "!=" => Some(CmpOp::Ne),
+                "<" => Some(CmpOp::Lt),
+                "<=" => Some(CmpOp::Le),
+                ">" => Some(CmpOp::Gt),
+                ">=" => Some(CmpOp::Ge),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.pos += 1;
+                return Ok(Expr::Cmp(
+                    Box::new(left),
+                    op,
+                    Box::new(self.parse_primary()?),
+                ));
+            }
+        }
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            self.expect_keyword("null")?;
+            let expr = Expr::IsNull(Box::new(left));
+            return Ok(if negated {
+                Expr::Not(Box::new(expr))
+            } else {
+                expr
+            });
+        }
+        let negated = self.keyword("not");
+        let expr = if self.keyword("like") {
+            match self.next() {
+                Some(Token::Str(pattern)) => Expr::Like(Box::new(left), pattern),
+                t => bail!("expect a pattern after LIKE, got {:?}", t),
+            }
+        } else if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut list = vec![self.parse_primary()?];
+            while self.symbol(",") {
+                list.push(self.parse_primary()?);
+            }
+            self.expect_symbol(")")?;
+            Expr::In(Box::new(left), list)
+        } else if negated {
+            bail!("expect LIKE or IN after NOT, got {:?}", self.peek());
+        } else {
+            return Ok(left);
+        };
+        Ok(if negated {
+            Expr::Not(Box::new(expr))
+        } else {
+            expr
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let expr = match self.next() {
+            Some(Token::Number(n)) => Expr::Value(Value::Number(n)),
+            Some(Token::Str(s)) => Expr::Value(Value::String(s)),
+            Some(Token::Symbol("-")) => match self.next() {
+                Some(Token::Number(n)) => Expr::Value(negate(&n)),
+                t => bail!("expect a number after -, got {:?}", t),
+            },
+            Some(Token::Symbol("(")) => {
+                let expr = self.parse_or()?;
+                self.expect_symbol(")")?;
+                expr
+            }
+            Some(Token::Word(word)) => match word.to_ascii_lowercase().as_str() {
+                "null" => Expr::Value(Value::Null),
+                "true" => Expr::Value(Value::Bool(true)),
+                "false" => Expr::Value(Value::Bool(false)),
+                _ if self.symbol("(") => {
+                    let func: AggFunc = word.parse()?;
+                    let arg = if self.symbol("*") {
+                        if func != AggFunc::Count {
+                            bail!("{}(*) is not supported, only count(*)", func);
+                        }
+                        None
+                    } else {
+                        Some(Box::new(self.parse_column()?))
+                    };
+                    self.expect_symbol(")")?;
+                    Expr::Agg(func, arg)
+                }
+                _ => {
+                    self.pos -= 1;
+                    self.parse_column()?
+                }
+            },
+            Some(Token::Ident(_)) => {
+                self.pos -= 1;
+                self.parse_column()?
+            }
+            t => bail!("expect a column or value, got {:?}", t),
+        };
+        Ok(expr)
+    }
+
+    /// `name`, `"Kit Number"` or `t.name`.
+    fn parse_column(&mut self) -> Result<Expr> {
+        let first = match self.next() {
+            Some(Token::Word(name) | Token::Ident(name)) => name,
+            t => bail!("expect a column, got {:?}", t),
+        };
+        if !self.symbol(".") {
+            return Ok(Expr::Name(None, first));
+        }
+        match self.next() {
+            Some(Token::Word(name) | Token::Ident(name)) => Ok(Expr::Name(Some(first), name)),
+            t => Err(anyhow!("expect a column after {}., got {:?}", first, t)),
+        }
+    }
+}
+
+fn negate(n: &Number) -> Value {
+    match (n.as_i64(), n.as_f64()) {
+        (Some(i), _) => Value::from(-i),
+        (None, Some(f)) => Value::from(-f),
+        _ => Value::Null,
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        if let Some(s) = ["<=", ">=", "<>", "!=", "=="].iter().find(|s| **s == two) {
+            tokens.push(Token::Symbol(if *s == "==" { "=" } else { s }));
+            i += 2;
+            continue;
+        }
+        if let Some(s) = ["=", "<", ">", ",", ".", "(", ")", "*", ";", "-"]
+            .iter()
+            .find(|s| s.starts_with(c))
+        {
+            tokens.push(Token::Symbol(s));
+            i += 1;
+            continue;
+        }
+        match c {
+            // a doubled quote escapes it
+            '\'' | '"' | '`' => {
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => bail!("unterminated {} in query", c),
+                        Some(ch) if *ch == c && chars.get(i + 1) == Some(&c) => {
+                            s.push(c);
+                            i += 2;
+                        }
+                        Some(ch) if *ch == c => break,
+                        Some(ch) => {
+                            s.push(*ch);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push(match c {
+                    '\'' => Token::Str(s),
+                    _ => Token::Ident(s),
+                });
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let s: String = chars[start..i].iter().collect();
+                let n = match s.parse::<u64>() {
+                    Ok(n) => Number::from(n),
+                    Err(_) => s
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(Number::from_f64)
+                        .ok_or_else(|| anyhow!("invalid number {} in query", s))?,
+                };
+                tokens.push(Token::Number(n));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+            }
+            c => bail!("unexpected character {} in query", c),
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+    use serde_json::json;
+
+    fn query(sql: &str) -> Result<Value> {
+        let fixture = Fixture::new()?;
+        let output = fixture.path("query.json");
+        process_csv_query(&csv_cmd!(Query, &["query", sql, "-o", &output]))?;
+        fixture.read_json("query.json")
+    }
+
+    #[test]
+    fn test_parse_query() -> Result<()> {
+        let query = Query::parse(
+            "select distinct p.Name as n, count(*) from 'a.csv' p left join b on p.id = b.id \
+             where \"Kit Number\" >= -1 and Name not like 'B%' group by 1 order by n desc limit 5;",
+        )?;
+        assert!(query.distinct);
+        assert_eq!(
+            query.items[0],
+            SelectItem::Expr(
+                Expr::Name(Some("p".into()), "Name".into()),
+                Some("n".into())
+            )
+        );
+        assert_eq!(
+            query.items[1],
+            SelectItem::Expr(Expr::Agg(AggFunc::Count, None), None)
+        );
+        assert_eq!(query.from.alias.as_deref(), Some("p"));
+        assert_eq!(query.joins[0].table.path, "b");
+        assert!(query.joins[0].left);
+        assert_eq!(query.order_by.len(), 1);
+        assert_eq!(query.limit, Some(5));
+
+        assert!(Query::parse("select * from").is_err());
+        assert!(Query::parse("select * from a where").is_err());
+        assert!(Query::parse("select sum(*) from a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_like() {
+        assert!(like("Buffon", "B%"));
+        assert!(like("Buffon", "%ff%"));
+        assert!(like("Buffon", "B_ffon"));
+        assert!(!like("Buffon", "%x%"));
+        assert!(like("aab", "%ab"));
+    }
+
+    #[test]
+    fn test_query_group_by() -> Result<()> {
+        let result = query(
+            "SELECT Position, count(*) FROM 'assets/juventus.csv' \
+             GROUP BY Position ORDER BY 2 DESC, Position LIMIT 2",
+        )?;
+        assert_eq!(
+            result,
+            json!([
+                {"Position": "Central Midfield", "count(*)": 6},
+                {"Position": "Centre-Back", "count(*)": 5},
+            ])
+        );
+
+        let result = query(
+            "SELECT count(*) AS players, max(\"Kit Number\") FROM 'assets/juventus.csv' \
+             WHERE Nationality IN ('Italy', 'Brazil')",
+        )?;
+        assert_eq!(result, json!([{"players": 11, "max(Kit Number)": 77}]));
+
+        let result = query(
+            "SELECT Position, count(*) FROM 'assets/juventus.csv' GROUP BY 1 ORDER BY 2 DESC LIMIT 1",
+        )?;
+        assert_eq!(
+            result,
+            json!([{"Position": "Central Midfield", "count(*)": 6}])
+        );
+        assert!(query("SELECT count(*) FROM 'assets/juventus.csv' GROUP BY 1").is_err());
+        assert!(query("SELECT Name FROM 'assets/juventus.csv' GROUP BY 2").is_err());
+        assert!(query("SELECT Name FROM 'assets/juventus.csv' WHERE count(*) > 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_where_order() -> Result<()> {
+        let result = query(
+            "SELECT Name, `Kit Number` AS kit FROM 'assets/juventus.csv' \
+             WHERE Position = 'Goalkeeper' AND NOT Name LIKE 'W%' ORDER BY kit DESC",
+        )?;
+        assert_eq!(
+            result,
+            json!([
+                {"Name": "Gianluigi Buffon", "kit": 77},
+                {"Name": "Mattia Perin", "kit": 37},
+                {"Name": "Carlo Pinsoglio", "kit": 31},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_join() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let players = fixture.write(
+            "players.csv",
+            "id,name,team\n1,Buffon,Juventus\n2,Pirlo,Juventus\n3,Totti,Roma\n",
+        )?;
+        let caps = fixture.write("caps.csv", "player_id,team,caps\n1,Italy,176\n3,Italy,58\n")?;
+        let sql = format!(
+            "SELECT p.name, c.team, caps FROM '{}' p LEFT JOIN '{}' c ON c.player_id = p.id \
+             ORDER BY caps DESC",
+            players, caps
+        );
+        assert_eq!(
+            query(&sql)?,
+            json!([
+                {"name": "Buffon", "team": "Italy", "caps": 176},
+                {"name": "Totti", "team": "Italy", "caps": 58},
+                {"name": "Pirlo", "team": null, "caps": null},
+            ])
+        );
+
+        let sql = format!(
+            "SELECT * FROM '{}' p JOIN '{}' c ON p.id = c.player_id WHERE caps > 100",
+            players, caps
+        );
+        assert_eq!(
+            query(&sql)?,
+            json!([{"id": 1, "name": "Buffon", "p.team": "Juventus", "player_id": 1, "c.team": "Italy", "caps": 176}])
+        );
+
+        let sql = format!(
+            "SELECT team FROM '{}' p JOIN '{}' c ON p.id = c.player_id",
+            players, caps
+        );
+        assert!(query(&sql).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_left_join_aggregate() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let players = fixture.write(
+            "players.csv",
+            "id,name,team\n1,Buffon,Juventus\n2,Pirlo,Juventus\n,Totti,Roma\n",
+        )?;
+        let caps = fixture.write("caps.csv", "player_id,caps\n1.0,176\n,58\n")?;
+        let sql = format!(
+            "SELECT p.team, count(*), count(caps), sum(caps) FROM '{}' p \
+             LEFT JOIN '{}' c ON p.id = c.player_id GROUP BY p.team ORDER BY 1",
+            players, caps
+        );
+        // 1 joins 1.0, the empty ids match nothing
+        assert_eq!(
+            query(&sql)?,
+            json!([
+                {"team": "Juventus", "count(*)": 2, "count(caps)": 1, "sum(caps)": 176},
+                {"team": "Roma", "count(*)": 1, "count(caps)": 0, "sum(caps)": 0},
+            ])
+        );
+        Ok(())
+    }
+}
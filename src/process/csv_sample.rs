@@ -0,0 +1,85 @@
+use super::csv_convert::{open_reader, open_writer, read_headers};
+use crate::cli::csv::CsvSampleOpts;
+use anyhow::{bail, Result};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Samples in a single pass, rows are written in their original order.
+pub fn process_csv_sample(opts: &CsvSampleOpts) -> Result<()> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let mut rng = match opts.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut writer = open_writer(&opts.output, &opts.read, &headers)?;
+
+    match (opts.rows, opts.fraction) {
+        (_, Some(p)) if !(0.0..=1.0).contains(&p) => bail!("fraction must be within 0 and 1"),
+        (_, Some(p)) => {
+            for record in reader.records() {
+                let record = record?;
+                if rng.gen_bool(p) {
+                    writer.write_record(&record)?;
+                }
+            }
+        }
+        (Some(n), None) => {
+            // reservoir sampling, algorithm R
+            let mut reservoir = Vec::with_capacity(n);
+            for (i, record) in reader.records().enumerate() {
+                let record = record?;
+                if reservoir.len() < n {
+                    reservoir.push((i, record));
+                } else {
+                    let j = rng.gen_range(0..=i);
+                    if j < n {
+                        reservoir[j] = (i, record);
+                    }
+                }
+            }
+            reservoir.sort_by_key(|(i, _)| *i);
+            for (_, record) in &reservoir {
+                writer.write_record(record)?;
+            }
+        }
+        (None, None) => bail!("either --rows or --fraction is required"),
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn sample(args: &[&str]) -> Result<Vec<String>> {
+        let fixture = Fixture::new()?;
+        let output = fixture.path("sample.csv");
+        let argv = [
+            &["sample", "-i", "assets/juventus.csv", "-o", &output],
+            args,
+        ]
+        .concat();
+        process_csv_sample(&csv_cmd!(Sample, &argv))?;
+        fixture.read_lines("sample.csv")
+    }
+
+    #[test]
+    fn test_reservoir_sample() -> Result<()> {
+        let rows = sample(&["--rows", "5", "--seed", "7"])?;
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows, sample(&["--rows", "5", "--seed", "7"])?);
+        assert_eq!(sample(&["--rows", "100"])?.len(), 28);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fraction_sample() -> Result<()> {
+        assert_eq!(sample(&["--fraction", "0"])?.len(), 1);
+        assert_eq!(sample(&["--fraction", "1"])?.len(), 28);
+        assert!(sample(&["--fraction", "2"]).is_err());
+        Ok(())
+    }
+}
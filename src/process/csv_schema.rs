@@ -0,0 +1,375 @@
+use super::csv_convert::{
+    infer_value, open_flexible_reader, open_reader, parse_date, read_headers,
+};
+use crate::cli::csv::{ColumnType, CsvSchemaOpts, CsvValidateOpts};
+use crate::get_reader;
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+const SCHEMA_DRAFT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Infers the type of a column from the values seen so far.
+#[derive(Debug, Default)]
+pub struct TypeInference {
+    ints: usize,
+    floats: usize,
+    bools: usize,
+    dates: usize,
+    strings: usize,
+    nulls: usize,
+}
+
+/// The schema of a single column, as understood by `validate` and `fake`.
+#[derive(Debug)]
+pub struct ColumnSchema {
+    pub name: String,
+    pub types: Vec<String>,
+    pub format: Option<String>,
+    pub values: Option<Vec<Value>>,
+    // `minimum`/`maximum`, or `formatMinimum`/`formatMaximum` for dates
+    pub minimum: Option<Value>,
+    pub maximum: Option<Value>,
+}
+
+pub fn process_csv_schema(opts: &CsvSchemaOpts) -> Result<Value> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let mut types: Vec<TypeInference> = headers.iter().map(|_| Default::default()).collect();
+    let mut distinct: Vec<Option<BTreeSet<String>>> = vec![Some(BTreeSet::new()); headers.len()];
+
+    for record in reader.records() {
+        let record = record?;
+        for (i, field) in record.iter().enumerate().take(headers.len()) {
+            types[i].observe(field);
+            if let Some(values) = &mut distinct[i] {
+                if !field.is_empty() {
+                    values.insert(field.to_string());
+                }
+                if values.len() > opts.max_enum {
+                    distinct[i] = None;
+                }
+            }
+        }
+    }
+
+    let mut properties = Map::new();
+    for ((name, inference), distinct) in headers.iter().zip(&types).zip(distinct) {
+        let ty = inference.column_type();
+        let mut prop = Map::new();
+        let json_type = json_type(ty);
+        if inference.nulls > 0 {
+            prop.insert("type".into(), json!([json_type, "null"]));
+        } else {
+            prop.insert("type".into(), json!(json_type));
+        }
+        if ty == ColumnType::Date {
+            prop.insert("format".into(), json!("date"));
+        }
+        // only repeated string values are worth an enum
+        let values = distinct.filter(|v| {
+            ty == ColumnType::String && !v.is_empty() && v.len() * 2 <= inference.count()
+        });
+        if let Some(values) = values {
+            prop.insert("enum".into(), json!(values));
+        }
+        properties.insert(name.into(), Value::Object(prop));
+    }
+
+    Ok(json!({
+        "$schema": SCHEMA_DRAFT,
+        "type": "array",
+        "items": {
+            "type": "object",
+            "properties": properties,
+            "required": headers.iter().collect::<Vec<_>>(),
+            "additionalProperties": false,
+        }
+    }))
+}
+
+/// Check every cell against the schema, returns one message per failure.
+/// Rows are named by the line they start on, rows with the wrong number of
+/// fields are reported and their cells still checked.
+pub fn process_csv_validate(opts: &CsvValidateOpts) -> Result<Vec<String>> {
+    let schema: Value = serde_json::from_reader(get_reader(&opts.schema)?)?;
+    let items = &schema["items"];
+    let properties = schema_properties(&schema)?;
+
+    let mut reader = open_flexible_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let mut errors = Vec::new();
+
+    let required = items["required"].as_array().cloned().unwrap_or_default();
+    for name in required.iter().filter_map(|v| v.as_str()) {
+        if !headers.iter().any(|h| h == name) {
+            errors.push(format!("header: missing required column {}", name));
+        }
+    }
+    let mut columns = Vec::with_capacity(headers.len());
+    for (i, name) in headers.iter().enumerate() {
+        match properties.get(name) {
+            Some(prop) => columns.push(Some(ColumnSchema::from_json(name, prop))),
+            None => {
+                if items["additionalProperties"] == json!(false) {
+                    errors.push(format!(
+                        "header, column {}: unexpected column {}",
+                        i + 1,
+                        name
+                    ));
+                }
+                columns.push(None);
+            }
+        }
+    }
+
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, |p| p.line());
+        if record.len() != headers.len() {
+            errors.push(format!(
+                "line {}: expected {} fields, got {}",
+                line,
+                headers.len(),
+                record.len()
+            ));
+        }
+        for (i, field) in record.iter().enumerate() {
+            if let Some(Some(column)) = columns.get(i) {
+                if let Err(e) = column.check(field) {
+                    errors.push(format!(
+                        "line {}, column {} ({}): {}",
+                        line,
+                        i + 1,
+                        column.name,
+                        e
+                    ));
+                }
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// The column properties of a schema written by `process_csv_schema`.
+pub fn schema_properties(schema: &Value) -> Result<&Map<String, Value>> {
+    schema["items"]["properties"]
+        .as_object()
+        .ok_or_else(|| anyhow!("schema has no items.properties"))
+}
+
+impl TypeInference {
+    pub fn observe(&mut self, field: &str) {
+        match infer_value(field) {
+            Value::Null => self.nulls += 1,
+            Value::Bool(_) => self.bools += 1,
+            Value::Number(n) if n.is_f64() => self.floats += 1,
+            Value::Number(_) => self.ints += 1,
+            _ if parse_date(field).is_some() => self.dates += 1,
+            _ => self.strings += 1,
+        }
+    }
+
+    /// Number of non-null values seen.
+    pub fn count(&self) -> usize {
+        self.ints + self.floats + self.bools + self.dates + self.strings
+    }
+
+    /// Whether every non-null value seen so far is a number.
+    pub fn is_numeric(&self) -> bool {
+        self.ints + self.floats == self.count()
+    }
+
+    /// Number of null (empty) values seen.
+    pub fn nulls(&self) -> usize {
+        self.nulls
+    }
+
+    /// The narrowest type that fits all non-null values.
+    pub fn column_type(&self) -> ColumnType {
+        let count = self.count();
+        if count == 0 || self.strings > 0 {
+            ColumnType::String
+        } else if self.ints == count {
+            ColumnType::Int
+        } else if self.ints + self.floats == count {
+            ColumnType::Float
+        } else if self.bools == count {
+            ColumnType::Bool
+        } else if self.dates == count {
+            ColumnType::Date
+        } else {
+            ColumnType::String
+        }
+    }
+}
+
+impl ColumnSchema {
+    pub fn from_json(name: &str, prop: &Value) -> Self {
+        let types = match &prop["type"] {
+            Value::String(s) => vec![s.clone()],
+            Value::Array(items) => items
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            name: name.into(),
+            types,
+            format: prop["format"].as_str().map(String::from),
+            values: prop["enum"].as_array().cloned(),
+            minimum: bound(prop, "minimum", "formatMinimum"),
+            maximum: bound(prop, "maximum", "formatMaximum"),
+        }
+    }
+
+    fn check(&self, field: &str) -> Result<()> {
+        if field.is_empty() {
+            if self.types.is_empty() || self.allows("null") {
+                return Ok(());
+            }
+            return Err(anyhow!("value is required"));
+        }
+        let value = infer_value(field);
+        let type_ok = self.types.is_empty()
+            || self.allows("string")
+            || (self.allows("integer") && value.is_i64())
+            || (self.allows("number") && value.is_number())
+            || (self.allows("boolean") && value.is_boolean());
+        if !type_ok {
+            return Err(anyhow!(
+                "expected {}, got {:?}",
+                self.types.join(" or "),
+                field
+            ));
+        }
+        if self.format.as_deref() == Some("date") && parse_date(field).is_none() {
+            return Err(anyhow!("expected a date, got {:?}", field));
+        }
+        if let Some(values) = &self.values {
+            if !values
+                .iter()
+                .any(|v| v.as_str() == Some(field) || *v == value)
+            {
+                return Err(anyhow!("{:?} is not one of the allowed values", field));
+            }
+        }
+        if let Some(min) = &self.minimum {
+            if self.compare(field, min) == Some(Ordering::Less) {
+                return Err(anyhow!("{:?} is below the minimum {}", field, min));
+            }
+        }
+        if let Some(max) = &self.maximum {
+            if self.compare(field, max) == Some(Ordering::Greater) {
+                return Err(anyhow!("{:?} is above the maximum {}", field, max));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, ty: &str) -> bool {
+        self.types.iter().any(|t| t == ty)
+    }
+
+    /// Compare a cell with a bound, as dates for date columns, else as numbers.
+    fn compare(&self, field: &str, bound: &Value) -> Option<Ordering> {
+        if self.format.as_deref() == Some("date") {
+            let bound = bound.as_str().and_then(parse_date)?;
+            return parse_date(field).map(|date| date.cmp(&bound));
+        }
+        field.parse::<f64>().ok()?.partial_cmp(&bound.as_f64()?)
+    }
+}
+
+fn bound(prop: &Value, key: &str, date_key: &str) -> Option<Value> {
+    let key = if prop["format"] == "date" {
+        date_key
+    } else {
+        key
+    };
+    prop.get(key).cloned()
+}
+
+fn json_type(ty: ColumnType) -> &'static str {
+    match ty {
+        ColumnType::Int => "integer",
+        ColumnType::Float => "number",
+        ColumnType::Bool => "boolean",
+        ColumnType::String | ColumnType::Date => "string",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    #[test]
+    fn test_type_inference() {
+        let infer = |values: &[&str]| {
+            let mut t = TypeInference::default();
+            values.iter().for_each(|v| t.observe(v));
+            t.column_type()
+        };
+        assert_eq!(infer(&["1", "2", ""]), ColumnType::Int);
+        assert_eq!(infer(&["1", "2.5"]), ColumnType::Float);
+        assert_eq!(infer(&["true", "FALSE"]), ColumnType::Bool);
+        assert_eq!(
+            infer(&["2019-01-01", "Apr 18, 1990 (29)"]),
+            ColumnType::Date
+        );
+        assert_eq!(infer(&["1", "x"]), ColumnType::String);
+        assert_eq!(infer(&["", ""]), ColumnType::String);
+    }
+
+    #[test]
+    fn test_column_schema_check() {
+        let column = ColumnSchema::from_json(
+            "Kit Number",
+            &json!({"type": ["integer", "null"], "enum": [1, 10]}),
+        );
+        assert!(column.check("10").is_ok());
+        assert!(column.check("").is_ok());
+        assert!(column.check("7").is_err());
+        assert!(column.check("ten").is_err());
+
+        let column = ColumnSchema::from_json("DOB", &json!({"type": "string", "format": "date"}));
+        assert!(column.check("Apr 18, 1990 (29)").is_ok());
+        assert!(column.check("").is_err());
+        assert!(column.check("yesterday").is_err());
+
+        let column = ColumnSchema::from_json(
+            "DOB",
+            &json!({"type": "string", "format": "date", "formatMinimum": "1990-01-01"}),
+        );
+        assert!(column.check("Apr 18, 1990 (29)").is_ok());
+        assert!(column.check("1989-12-31").is_err());
+        let column =
+            ColumnSchema::from_json("Kit Number", &json!({"type": "integer", "maximum": 99}));
+        assert!(column.check("99").is_ok());
+        assert!(column.check("100").is_err());
+    }
+
+    #[test]
+    fn test_validate_malformed_rows() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write("partner.csv", "a,b\n1,2\n3\n\"4\n\",5\nx,5\n")?;
+        let schema = fixture.write(
+            "schema.json",
+            &json!({"items": {"properties": {"a": {"type": "integer"}, "b": {"type": "integer"}}}})
+                .to_string(),
+        )?;
+        let argv = ["validate", "-i", &input, "--schema", &schema];
+        assert_eq!(
+            process_csv_validate(&csv_cmd!(Validate, &argv))?,
+            [
+                "line 3: expected 2 fields, got 1",
+                "line 4, column 1 (a): expected integer, got \"4\\n\"",
+                "line 6, column 1 (a): expected integer, got \"x\"",
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+use super::csv_convert::{open_reader, read_headers};
+use super::csv_layout::{is_number, pad, truncate};
+use crate::cli::csv::CsvShowOpts;
+use anyhow::Result;
+use csv::StringRecord;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use terminal_size::{terminal_size, Width};
+use unicode_width::UnicodeWidthStr;
+
+const DEFAULT_PAGER: &str = "less -FRSX";
+
+/// Render the records page by page, column widths are computed per page so
+/// memory stays bounded by the page size.
+pub fn process_csv_show(opts: &CsvShowOpts, mut writer: impl Write) -> Result<()> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let page_size = opts.page_size.max(1);
+    let (skip, take) = match (opts.page, opts.head) {
+        (Some(page), _) => (page.saturating_sub(1) * page_size, Some(page_size)),
+        (None, head) => (0, head),
+    };
+    let records = reader.records().skip(skip).take(take.unwrap_or(usize::MAX));
+
+    let width = terminal_size().map(|(Width(w), _)| w as usize);
+    let mut page = PageWriter {
+        headers: &headers,
+        opts,
+        width,
+        first: skip + 1,
+        pages: 0,
+    };
+    if let Some(n) = opts.tail {
+        let mut rows = VecDeque::new();
+        let mut seen = 0;
+        for record in records {
+            rows.push_back(record?);
+            seen += 1;
+            if rows.len() > n {
+                rows.pop_front();
+            }
+        }
+        page.first = seen - rows.len() + 1;
+        let rows = Vec::from(rows);
+        for chunk in rows.chunks(page_size) {
+            page.write(&mut writer, chunk)?;
+        }
+    } else {
+        let mut rows = Vec::with_capacity(page_size);
+        for record in records {
+            rows.push(record?);
+            if rows.len() == page_size {
+                page.write(&mut writer, &rows)?;
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            page.write(&mut writer, &rows)?;
+        }
+    }
+    if page.pages == 0 {
+        page.write(&mut writer, &[])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Start `$PAGER`, `less -FRSX` by default, `None` when it cannot be run.
+pub fn spawn_pager() -> Option<Child> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.into());
+    let mut args = pager.split_whitespace();
+    Command::new(args.next()?)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()
+}
+
+struct PageWriter<'a> {
+    headers: &'a StringRecord,
+    opts: &'a CsvShowOpts,
+    width: Option<usize>,
+    // number of the first record on the next page
+    first: usize,
+    pages: usize,
+}
+
+impl PageWriter<'_> {
+    fn write(&mut self, writer: &mut impl Write, rows: &[StringRecord]) -> Result<()> {
+        if self.pages > 0 {
+            writeln!(writer)?;
+        }
+        let table = render_table(self.headers, rows.iter(), self.opts.max_width);
+        let too_wide = self
+            .width
+            .is_some_and(|w| table.lines().next().map_or(0, |l| l.width()) > w);
+        if self.opts.vertical || too_wide {
+            let vertical = render_vertical(self.headers, rows.iter(), self.first, self.width);
+            writer.write_all(vertical.as_bytes())?;
+        } else {
+            writer.write_all(table.as_bytes())?;
+        }
+        self.first += rows.len();
+        self.pages += 1;
+        Ok(())
+    }
+}
+
+/// Render records as an aligned table, numbers are right aligned.
+pub fn render_table<'a>(
+    headers: &StringRecord,
+    rows: impl Iterator<Item = &'a StringRecord> + Clone,
+    max_width: usize,
+) -> String {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|h| truncate(h, max_width).width())
+        .collect();
+    for row in rows.clone() {
+        for (i, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(truncate(cell, max_width).width());
+        }
+    }
+
+    let mut out = String::new();
+    let line = |cells: Vec<String>| cells.join(" | ").trim_end().to_string() + "\n";
+    out += &line(
+        headers
+            .iter()
+            .zip(&widths)
+            .map(|(h, w)| pad(&truncate(h, max_width), *w, false))
+            .collect(),
+    );
+    out += &widths
+        .iter()
+        .map(|w| "-".repeat(*w))
+        .collect::<Vec<_>>()
+        .join("-+-");
+    out.push('\n');
+    for row in rows {
+        out += &line(
+            widths
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let cell = row.get(i).unwrap_or_default();
+                    pad(&truncate(cell, max_width), *w, is_number(cell))
+                })
+                .collect(),
+        );
+    }
+    out
+}
+
+/// Render every record as a block of `name | value` lines, numbered from `first`.
+pub fn render_vertical<'a>(
+    headers: &StringRecord,
+    rows: impl Iterator<Item = &'a StringRecord>,
+    first: usize,
+    term_width: Option<usize>,
+) -> String {
+    let label_width = headers.iter().map(|h| h.width()).max().unwrap_or(0);
+    let value_width = term_width.map_or(usize::MAX, |w| w.saturating_sub(label_width + 3).max(8));
+    let mut out = String::new();
+    for (n, row) in rows.enumerate() {
+        let title = format!("-[ RECORD {} ]", first + n);
+        out += &format!(
+            "{}{}\n",
+            title,
+            "-".repeat((label_width + 3).saturating_sub(title.len()))
+        );
+        for (i, name) in headers.iter().enumerate() {
+            let value = truncate(row.get(i).unwrap_or_default(), value_width);
+            let line = format!("{} | {}", pad(name, label_width, false), value);
+            out += line.trim_end();
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::csv_cmd;
+
+    #[test]
+    fn test_render_table() {
+        let headers = StringRecord::from(vec!["Name", "Kit Number"]);
+        let rows = [
+            StringRecord::from(vec!["Gianluigi Buffon", "77"]),
+            StringRecord::from(vec!["克里斯蒂亚诺·罗纳尔多", "7"]),
+        ];
+        assert_eq!(
+            render_table(&headers, rows.iter(), 12),
+            "Name         | Kit Number\n\
+             -------------+-----------\n\
+             Gianluigi B… |         77\n\
+             克里斯蒂亚…  |          7\n"
+        );
+    }
+
+    #[test]
+    fn test_render_vertical() {
+        let headers = StringRecord::from(vec!["Name", "Kit Number"]);
+        let rows = [StringRecord::from(vec!["Gianluigi Buffon", "77"])];
+        assert_eq!(
+            render_vertical(&headers, rows.iter(), 3, Some(20)),
+            "-[ RECORD 3 ]\n\
+             Name       | Gianlui…\n\
+             Kit Number | 77\n"
+        );
+    }
+
+    #[test]
+    fn test_show_pages() -> Result<()> {
+        let show = |args: &[&str]| -> Result<String> {
+            let argv = [&["show", "-i", "assets/juventus.csv", "--vertical"], args].concat();
+            let mut out = Vec::new();
+            process_csv_show(&csv_cmd!(Show, &argv), &mut out)?;
+            Ok(String::from_utf8(out)?)
+        };
+        let records = |out: &str| {
+            out.lines()
+                .filter_map(|l| l.strip_prefix("-[ RECORD "))
+                .map(|l| l.trim_end_matches(['-', ']', ' ']).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            records(&show(&["--page-size", "10", "--page", "3"])?),
+            ["21", "22", "23", "24", "25", "26", "27"]
+        );
+        assert_eq!(records(&show(&["--tail", "2"])?), ["26", "27"]);
+        assert_eq!(records(&show(&["--head", "1"])?), ["1"]);
+        assert!(records(&show(&["--page", "4", "--page-size", "10"])?).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+use super::csv_convert::{open_reader, open_writer, read_headers};
+use super::csv_filter::column_index;
+use crate::cli::csv::CsvSortOpts;
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder, StringRecord, WriterBuilder};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::rc::Rc;
+use tempfile::{NamedTempFile, TempPath};
+
+// runs merged at once, more runs are merged in several passes
+const MERGE_FAN_IN: usize = 64;
+
+/// Sorts in memory up to `buffer_rows`, beyond that sorted runs are spilled
+/// to temp files and merged. The sort is stable.
+pub struct ExternalSort<F> {
+    cmp: Rc<F>,
+    buffer_rows: usize,
+    fan_in: usize,
+    rows: Vec<StringRecord>,
+    runs: Vec<TempPath>,
+}
+
+/// The rows of an `ExternalSort`, in order.
+pub enum Sorted<F> {
+    Memory(std::vec::IntoIter<StringRecord>),
+    Merge(Merge<F>),
+}
+
+/// K-way merge of sorted runs, ties go to the earlier run.
+pub struct Merge<F> {
+    readers: Vec<Reader<File>>,
+    heap: BinaryHeap<Head<F>>,
+    // the files are removed once the merge is dropped
+    _runs: Vec<TempPath>,
+}
+
+struct Head<F> {
+    row: StringRecord,
+    run: usize,
+    cmp: Rc<F>,
+}
+
+pub fn process_csv_sort(opts: &CsvSortOpts) -> Result<()> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let keys = opts
+        .by
+        .iter()
+        .map(|key| Ok((column_index(&headers, &key.column)?, key.descending)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut sorter = ExternalSort::new(opts.buffer_rows, |a: &StringRecord, b: &StringRecord| {
+        compare_records(a, b, &keys)
+    });
+    for record in reader.records() {
+        sorter.push(record?)?;
+    }
+    let mut writer = open_writer(&opts.output, &opts.read, &headers)?;
+    for row in sorter.finish()? {
+        writer.write_record(&row?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> ExternalSort<F> {
+    pub fn new(buffer_rows: usize, cmp: F) -> Self {
+        Self {
+            cmp: Rc::new(cmp),
+            buffer_rows: buffer_rows.max(1),
+            fan_in: MERGE_FAN_IN,
+            rows: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: StringRecord) -> Result<()> {
+        self.rows.push(row);
+        if self.rows.len() >= self.buffer_rows {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Merges the runs `fan_in` at a time until a single merge is left.
+    pub fn finish(mut self) -> Result<Sorted<F>> {
+        if self.runs.is_empty() {
+            self.rows.sort_by(|a, b| (self.cmp)(a, b));
+            return Ok(Sorted::Memory(self.rows.into_iter()));
+        }
+        if !self.rows.is_empty() {
+            self.spill()?;
+        }
+        let mut runs = self.runs;
+        while runs.len() > self.fan_in {
+            // consecutive runs are merged together so ties keep the input order
+            let mut merged = Vec::new();
+            let mut rest = runs.into_iter().peekable();
+            while rest.peek().is_some() {
+                let mut group: Vec<TempPath> = rest.by_ref().take(self.fan_in).collect();
+                if group.len() == 1 {
+                    merged.append(&mut group);
+                } else {
+                    merged.push(write_run(Merge::new(group, self.cmp.clone())?)?);
+                }
+            }
+            runs = merged;
+        }
+        Ok(Sorted::Merge(Merge::new(runs, self.cmp)?))
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.rows.sort_by(|a, b| (self.cmp)(a, b));
+        let run = write_run(self.rows.drain(..).map(Ok))?;
+        self.runs.push(run);
+        Ok(())
+    }
+}
+
+impl<F> Iterator for Sorted<F>
+where
+    F: Fn(&StringRecord, &StringRecord) -> Ordering,
+{
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Sorted::Memory(rows) => rows.next().map(Ok),
+            Sorted::Merge(merge) => merge.next(),
+        }
+    }
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> Merge<F> {
+    fn new(runs: Vec<TempPath>, cmp: Rc<F>) -> Result<Self> {
+        let mut readers = runs
+            .iter()
+            .map(|path| {
+                Ok(ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .from_reader(File::open(path)?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some(row) = next_record(reader)? {
+                let cmp = cmp.clone();
+                heap.push(Head { row, run, cmp });
+            }
+        }
+        Ok(Self {
+            readers,
+            heap,
+            _runs: runs,
+        })
+    }
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> Iterator for Merge<F> {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let head = self.heap.pop()?;
+        match next_record(&mut self.readers[head.run]) {
+            Ok(Some(row)) => self.heap.push(Head {
+                row,
+                run: head.run,
+                cmp: head.cmp.clone(),
+            }),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(head.row))
+    }
+}
+
+// BinaryHeap is a max-heap, the order is reversed to pop the smallest row first
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> Ord for Head<F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.cmp)(&other.row, &self.row).then_with(|| other.run.cmp(&self.run))
+    }
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> PartialOrd for Head<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> PartialEq for Head<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<F: Fn(&StringRecord, &StringRecord) -> Ordering> Eq for Head<F> {}
+
+/// Empty cells sort first, then numbers by value, then text.
+pub fn compare_cells(a: &str, b: &str) -> Ordering {
+    let rank = |s: &str| match s.parse::<f64>() {
+        _ if s.is_empty() => (0, None),
+        Ok(v) if v.is_finite() => (1, Some(v)),
+        _ => (2, None),
+    };
+    match (rank(a), rank(b)) {
+        ((1, Some(x)), (1, Some(y))) => x.total_cmp(&y),
+        ((ra, _), (rb, _)) if ra != rb => ra.cmp(&rb),
+        _ => a.cmp(b),
+    }
+}
+
+fn compare_records(a: &StringRecord, b: &StringRecord, keys: &[(usize, bool)]) -> Ordering {
+    for (i, descending) in keys {
+        let ord = compare_cells(a.get(*i).unwrap_or_default(), b.get(*i).unwrap_or_default());
+        let ord = if *descending { ord.reverse() } else { ord };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Write sorted rows to a closed temp file, removed when the path is dropped.
+fn write_run(rows: impl Iterator<Item = Result<StringRecord>>) -> Result<TempPath> {
+    let mut file = NamedTempFile::new()?;
+    {
+        let mut writer = WriterBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_writer(&mut file);
+        for row in rows {
+            writer.write_record(&row?)?;
+        }
+        writer.flush()?;
+    }
+    Ok(file.into_temp_path())
+}
+
+fn next_record(reader: &mut Reader<File>) -> Result<Option<StringRecord>> {
+    let mut record = StringRecord::new();
+    if reader.read_record(&mut record)? {
+        Ok(Some(record))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn sort(args: &[&str]) -> Result<Vec<String>> {
+        let fixture = Fixture::new()?;
+        let output = fixture.path("sort.csv");
+        let argv = [&["sort", "-i", "assets/juventus.csv", "-o", &output], args].concat();
+        process_csv_sort(&csv_cmd!(Sort, &argv))?;
+        fixture.read_lines("sort.csv")
+    }
+
+    #[test]
+    fn test_compare_cells() {
+        assert_eq!(compare_cells("9", "10"), Ordering::Less);
+        assert_eq!(compare_cells("-1.5", "-1"), Ordering::Less);
+        assert_eq!(compare_cells("", "1"), Ordering::Less);
+        assert_eq!(compare_cells("10", "Buffon"), Ordering::Less);
+        assert_eq!(compare_cells("Buffon", "Allegri"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory() -> Result<()> {
+        let args = ["--by", "Position,Kit Number:desc"];
+        let in_memory = sort(&args)?;
+        let external = sort(&[&args[..], &["--buffer-rows", "4"]].concat())?;
+        assert_eq!(in_memory, external);
+        assert_eq!(in_memory.len(), 28);
+        assert_eq!(in_memory[0], "Name,Position,DOB,Nationality,Kit Number");
+        assert!(in_memory[1].starts_with("Rodrigo Bentancur,Central Midfield"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_pass_merge() -> Result<()> {
+        let mut sorter = ExternalSort::new(2, |a: &StringRecord, b: &StringRecord| {
+            compare_cells(&a[0], &b[0])
+        });
+        sorter.fan_in = 3;
+        let rows = [
+            "5", "3", "9", "1", "3", "7", "2", "8", "3", "6", "4", "0", "3",
+        ];
+        for (i, v) in rows.iter().enumerate() {
+            sorter.push(StringRecord::from(vec![*v, &i.to_string()]))?;
+        }
+        let sorted = sorter
+            .finish()?
+            .map(|row| Ok(row?.iter().collect::<Vec<_>>().join(":")))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            sorted,
+            [
+                "0:11", "1:3", "2:6", "3:1", "3:4", "3:8", "3:12", "4:10", "5:0", "6:9", "7:5",
+                "8:7", "9:2"
+            ]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use super::csv_convert::{open_reader, open_writer, read_headers};
+use super::csv_filter::column_index;
+use crate::cli::csv::CsvSplitOpts;
+use anyhow::{bail, Result};
+use csv::{Writer, WriterBuilder};
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// writers kept open by --by, the least recently used one is closed beyond that
+const MAX_OPEN_FILES: usize = 64;
+
+/// Open writers by file name, at most `capacity` of them.
+struct Writers {
+    capacity: usize,
+    open: HashMap<String, (Writer<Box<dyn Write>>, usize)>,
+    clock: usize,
+}
+
+/// File names by column value, values that sanitize to the same name get a
+/// hash suffix.
+#[derive(Default)]
+struct FileNames {
+    names: HashMap<String, String>,
+    // lowercased, for case insensitive file systems
+    taken: HashSet<String>,
+}
+
+/// Splits into files that each repeat the header, returns the written paths.
+pub fn process_csv_split(opts: &CsvSplitOpts) -> Result<Vec<PathBuf>> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let by = match &opts.by {
+        Some(name) => Some(column_index(&headers, name)?),
+        None => None,
+    };
+    let prefix = match &opts.prefix {
+        Some(prefix) => prefix.clone(),
+        None if opts.read.input == "-" => "split".into(),
+        None => Path::new(&opts.read.input)
+            .file_stem()
+            .map_or("split".into(), |s| s.to_string_lossy().into_owned()),
+    };
+    std::fs::create_dir_all(&opts.output_dir)?;
+    let path = |name: &str| Path::new(&opts.output_dir).join(format!("{}-{}.csv", prefix, name));
+
+    let mut files = Vec::new();
+    let mut created = HashSet::new();
+    let mut names = FileNames::default();
+    let mut writers = Writers::new(if by.is_some() { MAX_OPEN_FILES } else { 1 });
+    for (n, record) in reader.records().enumerate() {
+        let record = record?;
+        let name = match (by, opts.rows) {
+            (Some(i), _) => names.get(record.get(i).unwrap_or_default())?,
+            (None, Some(0)) => bail!("--rows must be greater than 0"),
+            (None, Some(rows)) => (n / rows + 1).to_string(),
+            (None, None) => bail!("either --rows or --by is required"),
+        };
+        let file = path(&name);
+        let writer = match writers.get(&name) {
+            Some(writer) => writer,
+            None if created.contains(&file) => {
+                // closed earlier to stay under the limit, continue where it stopped
+                let out = OpenOptions::new().append(true).open(&file)?;
+                let writer = WriterBuilder::new()
+                    .delimiter(opts.read.delimiter)
+                    .flexible(true)
+                    .from_writer(Box::new(out) as Box<dyn Write>);
+                writers.insert(name, writer)?
+            }
+            None => {
+                let writer = open_writer(&file.to_string_lossy(), &opts.read, &headers)?;
+                created.insert(file.clone());
+                files.push(file);
+                writers.insert(name, writer)?
+            }
+        };
+        writer.write_record(&record)?;
+    }
+    writers.close_all()?;
+    Ok(files)
+}
+
+impl Writers {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            open: HashMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<&mut Writer<Box<dyn Write>>> {
+        self.clock += 1;
+        let (writer, used) = self.open.get_mut(name)?;
+        *used = self.clock;
+        Some(writer)
+    }
+
+    fn insert(
+        &mut self,
+        name: String,
+        writer: Writer<Box<dyn Write>>,
+    ) -> Result<&mut Writer<Box<dyn Write>>> {
+        if self.open.len() >= self.capacity {
+            let oldest = self
+                .open
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(name, _)| name.clone());
+            if let Some((mut writer, _)) = oldest.and_then(|name| self.open.remove(&name)) {
+                writer.flush()?;
+            }
+        }
+        let (writer, _) = self.open.entry(name).or_insert((writer, self.clock));
+        Ok(writer)
+    }
+
+    fn close_all(self) -> Result<()> {
+        for (_, (mut writer, _)) in self.open {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl FileNames {
+    fn get(&mut self, value: &str) -> Result<String> {
+        if let Some(name) = self.names.get(value) {
+            return Ok(name.clone());
+        }
+        let mut name = file_name(value);
+        if self.taken.contains(&name.to_lowercase()) {
+            let hash = blake3::hash(value.as_bytes()).to_hex();
+            name = format!("{}-{}", name, &hash[..8]);
+            if self.taken.contains(&name.to_lowercase()) {
+                bail!("value {:?} maps to the file name of another value", value);
+            }
+        }
+        self.taken.insert(name.to_lowercase());
+        self.names.insert(value.to_string(), name.clone());
+        Ok(name)
+    }
+}
+
+/// Keep the value readable but safe as a file name.
+fn file_name(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.trim_matches('.') {
+        "" => "empty".into(),
+        name => name.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::{csv_cmd, Fixture};
+
+    fn split(fixture: &Fixture, args: &[&str]) -> Result<Vec<PathBuf>> {
+        let dir = fixture.path("split");
+        let argv = [
+            &["split", "-i", "assets/juventus.csv", "--output-dir", &dir],
+            args,
+        ]
+        .concat();
+        process_csv_split(&csv_cmd!(Split, &argv))
+    }
+
+    #[test]
+    fn test_split_by_rows() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let files = split(&fixture, &["--rows", "10"])?;
+        assert_eq!(files.len(), 3);
+        assert!(files[2].ends_with("juventus-3.csv"));
+        let content = std::fs::read_to_string(&files[2])?;
+        assert!(content.starts_with("Name,Position,DOB,Nationality,Kit Number\n"));
+        assert_eq!(content.lines().count(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let files = split(&fixture, &["--by", "Position", "--prefix", "pos"])?;
+        assert_eq!(files.len(), 10);
+        assert!(files[0].ends_with("pos-Goalkeeper.csv"));
+        assert_eq!(std::fs::read_to_string(&files[0])?.lines().count(), 5);
+        assert_eq!(file_name("Centre/Back "), "Centre_Back_");
+        assert_eq!(file_name(".."), "empty");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_reopens_and_dedupes_names() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let input = fixture.write("in.csv", "team,n\na/b,1\na_b,2\nA_B,3\na/b,4\n")?;
+        let dir = fixture.path("split");
+        let argv = ["split", "-i", &input, "--output-dir", &dir, "--by", "team"];
+        let files = process_csv_split(&csv_cmd!(Split, &argv))?;
+        assert_eq!(files.len(), 3);
+        assert!(files[0].ends_with("in-a_b.csv"));
+        let content = |i: usize| std::fs::read_to_string(&files[i]);
+        assert_eq!(content(0)?, "team,n\na/b,1\na/b,4\n");
+        assert_eq!(content(1)?, "team,n\na_b,2\n");
+        assert_eq!(content(2)?, "team,n\nA_B,3\n");
+
+        // more values than open files, every file is closed and reopened
+        let rows: String = (0..MAX_OPEN_FILES * 2 + 4)
+            .map(|i| format!("v{},{}\n", i % (MAX_OPEN_FILES + 2), i))
+            .collect();
+        let input = fixture.write("many.csv", &("team,n\n".to_string() + &rows))?;
+        let argv = ["split", "-i", &input, "--output-dir", &dir, "--by", "team"];
+        let files = process_csv_split(&csv_cmd!(Split, &argv))?;
+        assert_eq!(files.len(), MAX_OPEN_FILES + 2);
+        let first = std::fs::read_to_string(&files[0])?;
+        assert_eq!(first, format!("team,n\nv0,0\nv0,{}\n", MAX_OPEN_FILES + 2));
+        Ok(())
+    }
+}
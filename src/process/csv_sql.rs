@@ -0,0 +1,184 @@
+use super::csv_convert::parse_date;
+use super::csv_schema::TypeInference;
+use super::csv_writer::{cell_text, OutputTarget};
+use crate::cli::csv::{ColumnType, SqlDialect};
+use anyhow::Result;
+use serde_json::Value;
+use std::io::Write;
+
+/// Write a CREATE TABLE with the column types inferred from all records,
+/// followed by batched INSERTs. Columns are the keys of the first record.
+/// Unlike the other formats this needs every record up front.
+pub fn write_sql(writer: &mut impl Write, records: &[Value], target: &OutputTarget) -> Result<()> {
+    let Some(Value::Object(first)) = records.first() else {
+        writeln!(writer, "-- no records")?;
+        return Ok(());
+    };
+    let columns: Vec<&String> = first.keys().collect();
+    let mut types: Vec<TypeInference> = columns.iter().map(|_| Default::default()).collect();
+    for record in records {
+        for (column, inference) in columns.iter().zip(&mut types) {
+            inference.observe(&cell_text(&record[column.as_str()]));
+        }
+    }
+
+    let dialect = target.dialect;
+    let table = quote_ident(&target.name, dialect);
+    let definitions: Vec<String> = columns
+        .iter()
+        .zip(&types)
+        .map(|(column, inference)| {
+            let not_null = if inference.nulls() == 0 {
+                " NOT NULL"
+            } else {
+                ""
+            };
+            format!(
+                "  {} {}{}",
+                quote_ident(column, dialect),
+                sql_type(inference.column_type(), dialect),
+                not_null
+            )
+        })
+        .collect();
+    writeln!(
+        writer,
+        "CREATE TABLE {} (\n{}\n);",
+        table,
+        definitions.join(",\n")
+    )?;
+
+    let names: Vec<String> = columns.iter().map(|c| quote_ident(c, dialect)).collect();
+    let types: Vec<ColumnType> = types.iter().map(|t| t.column_type()).collect();
+    for batch in records.chunks(target.batch_size) {
+        let rows: Vec<String> = batch
+            .iter()
+            .map(|record| {
+                let values: Vec<String> = columns
+                    .iter()
+                    .zip(&types)
+                    .map(|(column, ty)| sql_value(&record[column.as_str()], *ty, dialect))
+                    .collect();
+                format!("  ({})", values.join(", "))
+            })
+            .collect();
+        writeln!(
+            writer,
+            "\nINSERT INTO {} ({}) VALUES\n{};",
+            table,
+            names.join(", "),
+            rows.join(",\n")
+        )?;
+    }
+    Ok(())
+}
+
+pub fn quote_ident(name: &str, dialect: SqlDialect) -> String {
+    match dialect {
+        SqlDialect::Mysql => format!("`{}`", name.replace('`', "``")),
+        SqlDialect::Postgres | SqlDialect::Sqlite => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+pub fn quote_str(s: &str, dialect: SqlDialect) -> String {
+    match dialect {
+        // mysql treats backslash as an escape character by default
+        SqlDialect::Mysql => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "''")),
+        SqlDialect::Postgres | SqlDialect::Sqlite => format!("'{}'", s.replace('\'', "''")),
+    }
+}
+
+fn sql_type(ty: ColumnType, dialect: SqlDialect) -> &'static str {
+    match (ty, dialect) {
+        (ColumnType::Int, SqlDialect::Sqlite) => "INTEGER",
+        (ColumnType::Int, _) => "BIGINT",
+        (ColumnType::Float, SqlDialect::Postgres) => "DOUBLE PRECISION",
+        (ColumnType::Float, SqlDialect::Mysql) => "DOUBLE",
+        (ColumnType::Float, SqlDialect::Sqlite) => "REAL",
+        (ColumnType::Bool, SqlDialect::Sqlite) => "INTEGER",
+        (ColumnType::Bool, _) => "BOOLEAN",
+        (ColumnType::Date, SqlDialect::Sqlite) => "TEXT",
+        (ColumnType::Date, _) => "DATE",
+        (ColumnType::String, _) => "TEXT",
+    }
+}
+
+fn sql_value(value: &Value, ty: ColumnType, dialect: SqlDialect) -> String {
+    let text = cell_text(value);
+    if text.is_empty() {
+        return "NULL".into();
+    }
+    match ty {
+        ColumnType::Int | ColumnType::Float => text,
+        ColumnType::Bool => {
+            let b = text.eq_ignore_ascii_case("true");
+            match dialect {
+                SqlDialect::Sqlite => (b as u8).to_string(),
+                _ => (if b { "TRUE" } else { "FALSE" }).into(),
+            }
+        }
+        // dates are normalized so the database can parse them
+        ColumnType::Date => match parse_date(&text) {
+            Some(date) => quote_str(&date.to_string(), dialect),
+            None => quote_str(&text, dialect),
+        },
+        ColumnType::String => quote_str(&text, dialect),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn to_sql(records: &[Value], dialect: SqlDialect) -> Result<String> {
+        let target = OutputTarget {
+            name: "players".into(),
+            dialect,
+            batch_size: 2,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        write_sql(&mut buf, records, &target)?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_write_sql() -> Result<()> {
+        let records = [
+            json!({"Name": "Gianluigi Buffon", "DOB": "Jan 28, 1978 (41)", "Kit Number": "77", "Retired": "true"}),
+            json!({"Name": "Giorgio Chiellini", "DOB": "Aug 14, 1984 (35)", "Kit Number": 3, "Retired": null}),
+            json!({"Name": "D'Angelo", "DOB": "2001-01-01", "Kit Number": "", "Retired": "false"}),
+        ];
+        assert_eq!(
+            to_sql(&records, SqlDialect::Postgres)?,
+            r#"CREATE TABLE "players" (
+  "Name" TEXT NOT NULL,
+  "DOB" DATE NOT NULL,
+  "Kit Number" BIGINT,
+  "Retired" BOOLEAN
+);
+
+INSERT INTO "players" ("Name", "DOB", "Kit Number", "Retired") VALUES
+  ('Gianluigi Buffon', '1978-01-28', 77, TRUE),
+  ('Giorgio Chiellini', '1984-08-14', 3, NULL);
+
+INSERT INTO "players" ("Name", "DOB", "Kit Number", "Retired") VALUES
+  ('D''Angelo', '2001-01-01', NULL, FALSE);
+"#
+        );
+        let sql = to_sql(&records, SqlDialect::Sqlite)?;
+        assert!(sql.contains(r#""Retired" INTEGER"#));
+        assert!(sql.contains("('Gianluigi Buffon', '1978-01-28', 77, 1)"));
+        assert_eq!(to_sql(&[], SqlDialect::Mysql)?, "-- no records\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote_ident("a\"b", SqlDialect::Postgres), "\"a\"\"b\"");
+        assert_eq!(quote_ident("a`b", SqlDialect::Mysql), "`a``b`");
+        assert_eq!(quote_str("it's \\", SqlDialect::Mysql), "'it''s \\\\'");
+        assert_eq!(quote_str("it's \\", SqlDialect::Sqlite), "'it''s \\'");
+    }
+}
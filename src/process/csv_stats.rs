@@ -0,0 +1,500 @@
+use super::csv_convert::{open_reader, parse_date, read_headers};
+use super::csv_schema::TypeInference;
+use super::csv_show::render_table;
+use crate::cli::csv::{ColumnType, CsvStatsOpts, ReportFormat};
+use anyhow::Result;
+use chrono::NaiveDate;
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// distinct values counted exactly per column, and the memory of the top-N counter
+const TRACKED_VALUES: usize = 10_000;
+// 4096 registers per column
+const HLL_BITS: u32 = 12;
+const SKETCH_CAPACITY: usize = 1024;
+
+#[derive(Debug, Serialize)]
+pub struct CsvStats {
+    pub rows: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Value,
+    pub max: Value,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub stddev: Option<f64>,
+    pub top: Vec<(String, usize)>,
+    /// `distinct`, `median` and the `top` counts are estimates.
+    pub approximate: bool,
+}
+
+/// Collects the statistics of one column in a single pass, memory is bounded
+/// whatever the size of the file.
+#[derive(Debug)]
+struct ColumnCollector {
+    types: TypeInference,
+    nulls: usize,
+    frequent: FrequentValues,
+    distinct: Option<DistinctCounter>,
+    // dropped once a non-numeric value shows up
+    numbers: Option<QuantileSketch>,
+    // Welford's online mean and variance
+    count: usize,
+    mean: f64,
+    m2: f64,
+    min_number: f64,
+    max_number: f64,
+    min_text: Option<String>,
+    max_text: Option<String>,
+    min_date: Option<NaiveDate>,
+    max_date: Option<NaiveDate>,
+}
+
+/// Misra-Gries summary of the most frequent values. Counts are exact until
+/// more than `capacity` distinct values are seen, lower bounds after that.
+#[derive(Debug)]
+struct FrequentValues {
+    capacity: usize,
+    counts: HashMap<String, usize>,
+}
+
+/// HyperLogLog estimate of the number of distinct values, about 1.6% error.
+#[derive(Debug)]
+struct DistinctCounter {
+    registers: Vec<u8>,
+}
+
+/// Quantile sketch keeping at most `SKETCH_CAPACITY` samples per level: a full
+/// level is sorted and every other sample moves up a level with twice the weight.
+#[derive(Debug, Default)]
+struct QuantileSketch {
+    levels: Vec<Vec<f64>>,
+    // alternate the kept half so compaction does not bias the estimate
+    odd: bool,
+}
+
+pub fn process_csv_stats(opts: &CsvStatsOpts) -> Result<String> {
+    let stats = collect_stats(opts)?;
+    match opts.format {
+        ReportFormat::Json => Ok(serde_json::to_string_pretty(&stats)? + "\n"),
+        ReportFormat::Table => Ok(render_stats(&stats)),
+    }
+}
+
+pub fn collect_stats(opts: &CsvStatsOpts) -> Result<CsvStats> {
+    let mut reader = open_reader(&opts.read)?;
+    let headers = read_headers(&mut reader, &opts.read.names)?;
+    let mut collectors: Vec<ColumnCollector> = headers
+        .iter()
+        .map(|_| ColumnCollector::new(opts.top))
+        .collect();
+    let mut rows = 0;
+    for record in reader.records() {
+        let record = record?;
+        rows += 1;
+        for (i, collector) in collectors.iter_mut().enumerate() {
+            collector.observe(record.get(i).unwrap_or_default());
+        }
+    }
+
+    let columns = headers
+        .iter()
+        .zip(collectors)
+        .map(|(name, collector)| collector.finish(name, opts.top))
+        .collect();
+    Ok(CsvStats { rows, columns })
+}
+
+impl ColumnCollector {
+    fn new(top: usize) -> Self {
+        Self {
+            types: TypeInference::default(),
+            nulls: 0,
+            frequent: FrequentValues::new(TRACKED_VALUES.max(top)),
+            distinct: None,
+            numbers: Some(QuantileSketch::default()),
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min_number: f64::INFINITY,
+            max_number: f64::NEG_INFINITY,
+            min_text: None,
+            max_text: None,
+            min_date: None,
+            max_date: None,
+        }
+    }
+
+    fn observe(&mut self, field: &str) {
+        self.types.observe(field);
+        if field.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        if !self.types.is_numeric() {
+            self.numbers = None;
+        }
+        if let Some(numbers) = &mut self.numbers {
+            if let Some(v) = field.parse::<f64>().ok().filter(|v| v.is_finite()) {
+                numbers.insert(v);
+                self.count += 1;
+                let delta = v - self.mean;
+                self.mean += delta / self.count as f64;
+                self.m2 += delta * (v - self.mean);
+                self.min_number = self.min_number.min(v);
+                self.max_number = self.max_number.max(v);
+            }
+        }
+        if let Some(date) = parse_date(field) {
+            self.min_date = Some(self.min_date.map_or(date, |d| d.min(date)));
+            self.max_date = Some(self.max_date.map_or(date, |d| d.max(date)));
+        }
+        if self.min_text.as_deref().is_none_or(|m| field < m) {
+            self.min_text = Some(field.to_string());
+        }
+        if self.max_text.as_deref().is_none_or(|m| field > m) {
+            self.max_text = Some(field.to_string());
+        }
+
+        if let Some(distinct) = &mut self.distinct {
+            distinct.observe(field);
+        } else if !self.frequent.would_track(field) {
+            // every distinct value seen so far is still tracked, seed the estimate with them
+            let mut distinct = DistinctCounter::new();
+            self.frequent
+                .counts
+                .keys()
+                .for_each(|v| distinct.observe(v));
+            distinct.observe(field);
+            self.distinct = Some(distinct);
+        }
+        self.frequent.observe(field);
+    }
+
+    fn finish(self, name: &str, top: usize) -> ColumnStats {
+        let ty = self.types.column_type();
+        let numbers = self.numbers.filter(|_| self.count > 0);
+        let mut approximate = self.distinct.is_some();
+        let (min, max, mean, median, stddev) = match numbers {
+            Some(numbers) if matches!(ty, ColumnType::Int | ColumnType::Float) => {
+                approximate |= !numbers.is_exact();
+                let stddev = if self.count > 1 {
+                    (self.m2 / (self.count - 1) as f64).sqrt()
+                } else {
+                    0.0
+                };
+                (
+                    number(self.min_number, ty),
+                    number(self.max_number, ty),
+                    Some(self.mean),
+                    numbers.median(),
+                    Some(stddev),
+                )
+            }
+            _ if ty == ColumnType::Date => {
+                let date = |d: Option<NaiveDate>| d.map_or(Value::Null, |d| json!(d.to_string()));
+                (date(self.min_date), date(self.max_date), None, None, None)
+            }
+            _ => (json!(self.min_text), json!(self.max_text), None, None, None),
+        };
+
+        let mut frequent: Vec<(String, usize)> = self.frequent.counts.into_iter().collect();
+        let distinct = match &self.distinct {
+            Some(counter) => counter.estimate(),
+            None => frequent.len(),
+        };
+        frequent.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        frequent.truncate(top);
+
+        ColumnStats {
+            name: name.into(),
+            ty: ty.to_string(),
+            nulls: self.nulls,
+            distinct,
+            min,
+            max,
+            mean,
+            median,
+            stddev,
+            top: frequent,
+            approximate,
+        }
+    }
+}
+
+impl FrequentValues {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Whether the value is counted without evicting anything.
+    fn would_track(&self, value: &str) -> bool {
+        self.counts.len() < self.capacity || self.counts.contains_key(value)
+    }
+
+    fn observe(&mut self, value: &str) {
+        if let Some(n) = self.counts.get_mut(value) {
+            *n += 1;
+        } else if self.counts.len() < self.capacity {
+            self.counts.insert(value.to_string(), 1);
+        } else {
+            // the new value and every tracked one lose a count, amortized O(1)
+            self.counts.retain(|_, n| {
+                *n -= 1;
+                *n > 0
+            });
+        }
+    }
+}
+
+impl DistinctCounter {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_BITS],
+        }
+    }
+
+    fn observe(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let idx = (hash >> (64 - HLL_BITS)) as usize;
+        let rank = ((hash << HLL_BITS) | (1 << (HLL_BITS - 1))).leading_zeros() as u8 + 1;
+        self.registers[idx] = self.registers[idx].max(rank);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+impl QuantileSketch {
+    fn insert(&mut self, v: f64) {
+        if self.levels.is_empty() {
+            self.levels.push(Vec::new());
+        }
+        self.levels[0].push(v);
+        let mut level = 0;
+        while self.levels[level].len() >= SKETCH_CAPACITY {
+            let mut samples = std::mem::take(&mut self.levels[level]);
+            samples.sort_by(f64::total_cmp);
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            let offset = self.odd as usize;
+            self.odd = !self.odd;
+            self.levels[level + 1].extend(samples.into_iter().skip(offset).step_by(2));
+            level += 1;
+        }
+    }
+
+    /// No sample has been dropped yet.
+    fn is_exact(&self) -> bool {
+        self.levels.len() <= 1
+    }
+
+    fn median(&self) -> Option<f64> {
+        if self.is_exact() {
+            let mut samples = self.levels.first()?.clone();
+            samples.sort_by(f64::total_cmp);
+            let n = samples.len();
+            return if n % 2 == 1 {
+                Some(samples[n / 2])
+            } else {
+                Some((samples[n / 2 - 1] + samples[n / 2]) / 2.0)
+            };
+        }
+        self.quantile(0.5)
+    }
+
+    /// The sample at rank `q` of the weighted samples.
+    fn quantile(&self, q: f64) -> Option<f64> {
+        let mut weighted: Vec<(f64, u64)> = self
+            .levels
+            .iter()
+            .enumerate()
+            .flat_map(|(level, samples)| samples.iter().map(move |v| (*v, 1u64 << level)))
+            .collect();
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total: u64 = weighted.iter().map(|(_, w)| w).sum();
+        let target = (q * total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (v, w) in &weighted {
+            seen += w;
+            if seen >= target {
+                return Some(*v);
+            }
+        }
+        weighted.last().map(|(v, _)| *v)
+    }
+}
+
+fn number(v: f64, ty: ColumnType) -> Value {
+    if ty == ColumnType::Int {
+        json!(v as i64)
+    } else {
+        json!(v)
+    }
+}
+
+fn render_stats(stats: &CsvStats) -> String {
+    let headers = StringRecord::from(vec![
+        "column", "type", "nulls", "distinct", "min", "max", "mean", "median", "stddev", "top",
+    ]);
+    let text = |v: &Value| match v {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    let float = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_default();
+    let rows: Vec<StringRecord> = stats
+        .columns
+        .iter()
+        .map(|c| {
+            let approx = |s: String| {
+                if c.approximate && !s.is_empty() {
+                    format!("~{}", s)
+                } else {
+                    s
+                }
+            };
+            let top = c
+                .top
+                .iter()
+                .map(|(v, n)| format!("{} ({})", v, n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            StringRecord::from(vec![
+                c.name.clone(),
+                c.ty.clone(),
+                c.nulls.to_string(),
+                approx(c.distinct.to_string()),
+                text(&c.min),
+                text(&c.max),
+                float(c.mean),
+                approx(float(c.median)),
+                float(c.stddev),
+                top,
+            ])
+        })
+        .collect();
+    format!(
+        "rows: {}\n{}",
+        stats.rows,
+        render_table(&headers, rows.iter(), 48)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::test_utils::csv_cmd;
+
+    #[test]
+    fn test_collect_stats() -> Result<()> {
+        let opts = csv_cmd!(Stats, &["stats", "-i", "assets/juventus.csv", "--top", "2"]);
+        let stats = collect_stats(&opts)?;
+        assert_eq!(stats.rows, 27);
+
+        let kit = &stats.columns[4];
+        assert_eq!(kit.name, "Kit Number");
+        assert_eq!(kit.ty, "int");
+        assert_eq!(kit.min, json!(1));
+        assert_eq!(kit.max, json!(77));
+        assert_eq!(kit.distinct, 27);
+        assert!(kit.mean.is_some() && kit.stddev.is_some());
+
+        let nationality = &stats.columns[3];
+        assert_eq!(nationality.ty, "string");
+        assert_eq!(nationality.top[0].0, "Italy");
+        assert_eq!(nationality.top.len(), 2);
+        assert!(nationality.mean.is_none());
+
+        let dob = &stats.columns[2];
+        assert_eq!(dob.ty, "date");
+        assert_eq!(dob.min, json!("1978-01-28"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_collector() {
+        let mut c = ColumnCollector::new(1);
+        for v in ["1", "2", "3", "4", ""] {
+            c.observe(v);
+        }
+        let stats = c.finish("n", 1);
+        assert_eq!(stats.nulls, 1);
+        assert_eq!(stats.mean, Some(2.5));
+        assert_eq!(stats.median, Some(2.5));
+        assert!((stats.stddev.unwrap() - 1.2909944).abs() < 1e-6);
+        assert_eq!(stats.top, vec![("1".to_string(), 1)]);
+        assert!(!stats.approximate);
+    }
+
+    #[test]
+    fn test_bounded_collector() {
+        let mut c = ColumnCollector::new(3);
+        let n = 200_000;
+        // a few heavy hitters among many unique values
+        let mut values: Vec<usize> = (0..n).map(|i| if i % 4 == 0 { i % 3 } else { i }).collect();
+        for v in &values {
+            c.observe(&v.to_string());
+        }
+        values.sort();
+        assert!(c.frequent.counts.len() <= TRACKED_VALUES);
+        assert!(c
+            .numbers
+            .as_ref()
+            .unwrap()
+            .levels
+            .iter()
+            .all(|l| l.len() < SKETCH_CAPACITY));
+
+        let stats = c.finish("n", 3);
+        assert!(stats.approximate);
+        let expected = (n - n / 4 + 3) as f64;
+        assert!((stats.distinct as f64 - expected).abs() / expected < 0.05);
+        let median = stats.median.unwrap();
+        let rank = values.partition_point(|v| (*v as f64) < median);
+        assert!(rank.abs_diff(n / 2) < n / 50);
+        let mut top: Vec<&str> = stats.top.iter().map(|(v, _)| v.as_str()).collect();
+        top.sort();
+        assert_eq!(top, ["0", "1", "2"]);
+    }
+
+    #[test]
+    fn test_numbers_dropped_for_text() {
+        let mut c = ColumnCollector::new(1);
+        for v in ["1", "Buffon", "2"] {
+            c.observe(v);
+        }
+        assert!(c.numbers.is_none());
+        let stats = c.finish("n", 1);
+        assert_eq!(stats.ty, "string");
+        assert_eq!(stats.median, None);
+    }
+}
@@ -0,0 +1,366 @@
+use super::csv_markup::{write_markdown, HtmlWriter};
+use super::csv_sql::write_sql;
+use crate::cli::csv::{CsvFormatOpts, OutputFormat, SqlDialect};
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const TOML_TABLE: &str = "records";
+const DEFAULT_NAME: &str = "data";
+// indefinite-length array, so records can be written as they come
+const CBOR_ARRAY_START: u8 = 0x9f;
+const CBOR_BREAK: u8 = 0xff;
+// one csv record is encoded at a time, longer ones are flushed in parts
+const CSV_BUFFER: usize = 1024;
+
+/// Settings of the formats that need more than the records, the name is the
+/// sql table, the title the html page title.
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    pub name: String,
+    pub title: String,
+    pub dialect: SqlDialect,
+    pub batch_size: usize,
+    pub standalone: bool,
+}
+
+/// Writes records one by one in the given format, so memory stays flat
+/// no matter how many records are written.
+///
+/// MessagePack needs the array length upfront, so its encoded records are
+/// spooled to a temporary file until `finish`. SQL and Markdown buffer the
+/// records in memory to infer column types and widths.
+pub struct RecordWriter<W: Write> {
+    inner: W,
+    format: OutputFormat,
+    count: usize,
+    spool: Option<BufWriter<File>>,
+    // csv header, given upfront or taken from the keys of the first record
+    columns: Vec<String>,
+    target: OutputTarget,
+    records: Vec<Value>,
+    html: HtmlWriter,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(inner: W, format: OutputFormat) -> Self {
+        Self {
+            inner,
+            format,
+            count: 0,
+            spool: None,
+            columns: Vec::new(),
+            target: OutputTarget::default(),
+            records: Vec::new(),
+            html: HtmlWriter::default(),
+        }
+    }
+
+    pub fn with_target(mut self, target: OutputTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Set the csv header instead of taking it from the first record.
+    pub fn with_columns(mut self, columns: Vec<String>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn write(&mut self, value: &Value) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                // same layout as serde_json::to_string_pretty on the whole array
+                let prefix = if self.count == 0 { "[\n  " } else { ",\n  " };
+                self.inner.write_all(prefix.as_bytes())?;
+                let content = serde_json::to_string_pretty(value)?;
+                self.inner
+                    .write_all(content.replace('\n', "\n  ").as_bytes())?;
+            }
+            OutputFormat::Yaml => {
+                serde_yaml::to_writer(&mut self.inner, &[value])?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.inner, value)?;
+                self.inner.write_all(b"\n")?;
+            }
+            OutputFormat::Toml => {
+                // every record becomes a [[records]] table, toml has no null
+                if self.count > 0 {
+                    self.inner.write_all(b"\n")?;
+                }
+                let record = strip_nulls(value).ok_or_else(|| {
+                    anyhow!(
+                        "record {} has a null in an array, toml has no null",
+                        self.count + 1
+                    )
+                })?;
+                let doc = serde_json::json!({ TOML_TABLE: [record] });
+                self.inner.write_all(toml::to_string(&doc)?.as_bytes())?;
+            }
+            OutputFormat::Msgpack => {
+                let spool = match &mut self.spool {
+                    Some(spool) => spool,
+                    None => self.spool.insert(BufWriter::new(tempfile::tempfile()?)),
+                };
+                rmp_serde::encode::write_named(spool, value)?;
+            }
+            OutputFormat::Cbor => {
+                if self.count == 0 {
+                    self.inner.write_all(&[CBOR_ARRAY_START])?;
+                }
+                ciborium::into_writer(value, &mut self.inner)?;
+            }
+            OutputFormat::Csv => {
+                let Value::Object(obj) = value else {
+                    bail!("csv output needs records to be objects");
+                };
+                if self.count == 0 {
+                    if self.columns.is_empty() {
+                        self.columns = obj.keys().cloned().collect();
+                    }
+                    write_csv_record(&mut self.inner, &self.columns)?;
+                }
+                let known = self.columns.iter().filter(|c| obj.contains_key(*c)).count();
+                if known < obj.len() {
+                    let key = obj.keys().find(|k| !self.columns.contains(k)).unwrap();
+                    bail!(
+                        "record {} has column {} which is not in the csv header",
+                        self.count + 1,
+                        key
+                    );
+                }
+                let cells = self
+                    .columns
+                    .iter()
+                    .map(|c| cell_text(obj.get(c).unwrap_or(&Value::Null)));
+                write_csv_record(&mut self.inner, cells)?;
+            }
+            OutputFormat::Sql | OutputFormat::Markdown => self.records.push(value.clone()),
+            OutputFormat::Html => {
+                if self.count == 0 {
+                    self.html.start(&mut self.inner, value, &self.target)?;
+                }
+                self.html.row(&mut self.inner, value)?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        match self.format {
+            OutputFormat::Json if self.count == 0 => self.inner.write_all(b"[]")?,
+            OutputFormat::Json => self.inner.write_all(b"\n]")?,
+            OutputFormat::Yaml if self.count == 0 => self.inner.write_all(b"[]\n")?,
+            OutputFormat::Toml if self.count == 0 => {
+                writeln!(self.inner, "{} = []", TOML_TABLE)?;
+            }
+            OutputFormat::Msgpack => {
+                let len = u32::try_from(self.count)
+                    .map_err(|_| anyhow!("msgpack arrays hold at most {} records", u32::MAX))?;
+                rmp::encode::write_array_len(&mut self.inner, len)?;
+                if let Some(spool) = self.spool.take() {
+                    let mut file = spool.into_inner().map_err(|e| e.into_error())?;
+                    file.seek(SeekFrom::Start(0))?;
+                    io::copy(&mut file, &mut self.inner)?;
+                }
+            }
+            OutputFormat::Cbor => {
+                if self.count == 0 {
+                    self.inner.write_all(&[CBOR_ARRAY_START])?;
+                }
+                self.inner.write_all(&[CBOR_BREAK])?;
+            }
+            OutputFormat::Sql => write_sql(&mut self.inner, &self.records, &self.target)?,
+            OutputFormat::Markdown => write_markdown(&mut self.inner, &self.records)?,
+            OutputFormat::Html => {
+                if self.count == 0 {
+                    self.html
+                        .start(&mut self.inner, &Value::Null, &self.target)?;
+                }
+                self.html.finish(&mut self.inner, &self.target)?;
+            }
+            _ => {}
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl OutputTarget {
+    /// Named after the input file unless `--table` or `--title` is given.
+    pub fn new(opts: &CsvFormatOpts, input: &str) -> Self {
+        let stem = match input {
+            "-" => DEFAULT_NAME.into(),
+            input => Path::new(input)
+                .file_stem()
+                .map_or(DEFAULT_NAME.into(), |s| s.to_string_lossy().into_owned()),
+        };
+        Self {
+            name: opts.table.clone().unwrap_or_else(|| stem.clone()),
+            title: opts.title.clone().unwrap_or(stem),
+            dialect: opts.dialect,
+            batch_size: opts.batch_size.max(1),
+            standalone: opts.standalone,
+        }
+    }
+}
+
+impl Default for OutputTarget {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_NAME.into(),
+            title: DEFAULT_NAME.into(),
+            dialect: SqlDialect::Postgres,
+            batch_size: 500,
+            standalone: false,
+        }
+    }
+}
+
+pub fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn write_csv_record<I>(writer: &mut impl Write, record: I) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[u8]>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .buffer_capacity(CSV_BUFFER)
+        .from_writer(writer);
+    writer.write_record(record)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Drop the null members of objects, `None` when an array holds a null since
+/// dropping it would shift the elements after it.
+fn strip_nulls(value: &Value) -> Option<Value> {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| Some((k.clone(), strip_nulls(v)?)))
+            .collect(),
+        Value::Array(items) => items
+            .iter()
+            .map(|v| if v.is_null() { None } else { strip_nulls(v) })
+            .collect(),
+        v => Some(v.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn write_bytes(values: &[Value], format: OutputFormat) -> Result<Vec<u8>> {
+        let mut writer = RecordWriter::new(Vec::new(), format);
+        for v in values {
+            writer.write(v)?;
+        }
+        writer.finish()
+    }
+
+    fn write_all(values: &[Value], format: OutputFormat) -> Result<String> {
+        Ok(String::from_utf8(write_bytes(values, format)?)?)
+    }
+
+    #[test]
+    fn test_record_writer_matches_buffered_output() -> Result<()> {
+        let values = vec![
+            json!({"name": "Del Piero", "kit": 10, "tags": ["a", "b"]}),
+            json!({"name": "Buffon", "kit": 1, "tags": []}),
+        ];
+        for values in [&values[..], &values[..1], &[]] {
+            assert_eq!(
+                write_all(values, OutputFormat::Json)?,
+                serde_json::to_string_pretty(values)?
+            );
+            assert_eq!(
+                write_all(values, OutputFormat::Yaml)?,
+                serde_yaml::to_string(values)?
+            );
+        }
+        assert_eq!(
+            write_all(&values, OutputFormat::Ndjson)?,
+            "{\"name\":\"Del Piero\",\"kit\":10,\"tags\":[\"a\",\"b\"]}\n{\"name\":\"Buffon\",\"kit\":1,\"tags\":[]}\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_writer_binary_formats() -> Result<()> {
+        let values = [
+            json!({"name": "Del Piero", "kit": 10, "tags": ["a", "b"]}),
+            json!({"name": "Buffon", "kit": 1, "tags": []}),
+        ];
+        for values in [&values[..], &[]] {
+            let bytes = write_bytes(values, OutputFormat::Msgpack)?;
+            assert_eq!(rmp_serde::from_slice::<Vec<Value>>(&bytes)?, values);
+            let bytes = write_bytes(values, OutputFormat::Cbor)?;
+            assert_eq!(ciborium::from_reader::<Vec<Value>, _>(&bytes[..])?, values);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_writer_toml() -> Result<()> {
+        let values = [
+            json!({"name": "Del Piero", "kit": 10, "owner": {"name": "Agnelli"}}),
+            json!({"name": "Buffon", "kit": null}),
+        ];
+        let content = write_all(&values, OutputFormat::Toml)?;
+        let doc: toml::Value = toml::from_str(&content)?;
+        let records = doc["records"].as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["owner"]["name"].as_str(), Some("Agnelli"));
+        assert!(records[1].get("kit").is_none());
+        assert_eq!(write_all(&[], OutputFormat::Toml)?, "records = []\n");
+
+        let err = write_all(&[json!({"tags": [1, null, 3]})], OutputFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "record 1 has a null in an array, toml has no null"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_writer_csv() -> Result<()> {
+        let values = [
+            json!({"name": "Del Piero, Alessandro", "kit": 10, "tags": ["a"]}),
+            json!({"name": "Buffon \"Gigi\"", "kit": null}),
+        ];
+        assert_eq!(
+            write_all(&values, OutputFormat::Csv)?,
+            "name,kit,tags\n\"Del Piero, Alessandro\",10,\"[\"\"a\"\"]\"\n\"Buffon \"\"Gigi\"\"\",,\n"
+        );
+
+        let values = [
+            json!({"name": "Buffon"}),
+            json!({"name": "Pirlo", "kit": 21}),
+        ];
+        assert!(write_all(&values, OutputFormat::Csv).is_err());
+        let mut writer = RecordWriter::new(Vec::new(), OutputFormat::Csv)
+            .with_columns(vec!["name".into(), "kit".into()]);
+        for v in &values {
+            writer.write(v)?;
+        }
+        assert_eq!(
+            String::from_utf8(writer.finish()?)?,
+            "name,kit\nBuffon,\nPirlo,21\n"
+        );
+        Ok(())
+    }
+}
@@ -1,12 +1,53 @@
 mod b64;
+mod csv_cat;
 mod csv_convert;
+mod csv_crypt;
+mod csv_dedupe;
+mod csv_diff;
+mod csv_encoding;
+mod csv_fake;
+mod csv_filter;
+mod csv_from;
+mod csv_group;
+mod csv_join;
+mod csv_layout;
+mod csv_markup;
+mod csv_mask;
+mod csv_parallel;
+mod csv_query;
+mod csv_sample;
+mod csv_schema;
+mod csv_show;
+mod csv_sort;
+mod csv_split;
+mod csv_sql;
+mod csv_stats;
+mod csv_writer;
 mod gen_pass;
 mod http_serve;
 mod jwt;
+#[cfg(test)]
+mod test_utils;
 mod text;
 
 pub use b64::{process_decode, process_encode};
+pub use csv_cat::process_csv_cat;
 pub use csv_convert::process_csv;
+pub use csv_crypt::{process_csv_decrypt, process_csv_encrypt};
+pub use csv_dedupe::process_csv_dedupe;
+pub use csv_diff::process_csv_diff;
+pub use csv_fake::process_csv_fake;
+pub use csv_from::process_csv_from;
+pub use csv_group::process_csv_group;
+pub use csv_join::process_csv_join;
+pub use csv_mask::process_csv_mask;
+pub use csv_query::process_csv_query;
+pub use csv_sample::process_csv_sample;
+pub use csv_schema::{process_csv_schema, process_csv_validate};
+pub use csv_show::{process_csv_show, spawn_pager};
+pub use csv_sort::process_csv_sort;
+pub use csv_split::process_csv_split;
+pub use csv_stats::process_csv_stats;
 pub use gen_pass::process_genpass;
 pub use http_serve::process_http;
 pub use jwt::*;
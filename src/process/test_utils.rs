@@ -0,0 +1,62 @@
+use crate::cli::csv::{CsvConvertOpts, CsvOpts};
+use anyhow::Result;
+use clap::Parser;
+use serde_json::Value;
+use tempfile::TempDir;
+
+/// A private temporary directory for the files of one test, removed on drop.
+pub struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            dir: tempfile::tempdir()?,
+        })
+    }
+
+    pub fn path(&self, name: &str) -> String {
+        self.dir.path().join(name).to_string_lossy().into_owned()
+    }
+
+    /// Write `content` to `name` in the fixture, returns its path.
+    pub fn write(&self, name: &str, content: &str) -> Result<String> {
+        let path = self.path(name);
+        std::fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    pub fn read(&self, name: &str) -> Result<String> {
+        Ok(std::fs::read_to_string(self.path(name))?)
+    }
+
+    pub fn read_lines(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self.read(name)?.lines().map(String::from).collect())
+    }
+
+    pub fn read_json(&self, name: &str) -> Result<Value> {
+        Ok(serde_json::from_str(&self.read(name)?)?)
+    }
+}
+
+/// Parse the arguments of `rcli csv`.
+pub fn csv_opts(args: &[&str]) -> CsvOpts {
+    CsvOpts::parse_from(std::iter::once("csv").chain(args.iter().copied()))
+}
+
+/// Parse the arguments of `rcli csv` without a subcommand.
+pub fn convert_opts(args: &[&str]) -> CsvConvertOpts {
+    csv_opts(args).convert
+}
+
+/// Parse `rcli csv <args>` into the options of the given subcommand.
+macro_rules! csv_cmd {
+    ($cmd:ident, $args:expr) => {
+        match $crate::process::test_utils::csv_opts($args).cmd {
+            Some($crate::cli::csv::CsvSubCommand::$cmd(opts)) => opts,
+            _ => panic!(concat!("expect csv ", stringify!($cmd))),
+        }
+    };
+}
+pub(crate) use csv_cmd;
@@ -1,9 +1,9 @@
 use std::{fs, io::Read, path::Path};
 
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chacha20poly1305::{
-    aead::{generic_array::GenericArray, Aead, KeyInit, OsRng},
+    aead::{generic_array::GenericArray, Aead, AeadCore, KeyInit, OsRng, Payload},
     ChaCha20Poly1305,
 };
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
@@ -123,6 +123,8 @@ impl KeyLoader for Ed25519Verifier {
 //     fn decrypt(&self, reader: &mut dyn Read) -> Result<String>;
 // }
 
+const NONCE_LEN: usize = 12;
+
 pub struct Chacha {
     key: [u8; 32],
     nonce: chacha20poly1305::Nonce,
@@ -153,6 +155,37 @@ impl Chacha {
         let result = cipher.decrypt(&self.nonce, input.as_ref()).unwrap();
         Ok(String::from_utf8(result).unwrap())
     }
+
+    /// Encrypt with a fresh random nonce, the nonce is prepended to the
+    /// base64url ciphertext. `aad` is authenticated but not encrypted, `open`
+    /// must be given the same.
+    pub fn seal(&self, input: impl AsRef<[u8]>, aad: &[u8]) -> Result<String> {
+        let cipher = ChaCha20Poly1305::new_from_slice(&self.key)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: input.as_ref(),
+            aad,
+        };
+        let result = cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| anyhow!("encryption failed"))?;
+        Ok(URL_SAFE_NO_PAD.encode([nonce.as_slice(), &result].concat()))
+    }
+
+    /// Decrypt the output of `seal`.
+    pub fn open(&self, input: impl AsRef<[u8]>, aad: &[u8]) -> Result<String> {
+        let input = URL_SAFE_NO_PAD.decode(input)?;
+        if input.len() < NONCE_LEN {
+            return Err(anyhow!("ciphertext is too short"));
+        }
+        let (nonce, data) = input.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new_from_slice(&self.key)?;
+        let payload = Payload { msg: data, aad };
+        let result = cipher
+            .decrypt(GenericArray::from_slice(nonce), payload)
+            .map_err(|_| anyhow!("wrong key or corrupted ciphertext"))?;
+        Ok(String::from_utf8(result)?)
+    }
 }
 
 pub fn process_encrypt(input: &str, key: &str) -> Result<String> {
@@ -1,6 +1,6 @@
 use anyhow::Error;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 
 pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>, Error> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -10,3 +10,12 @@ pub fn get_reader(input: &str) -> anyhow::Result<Box<dyn Read>, Error> {
     };
     Ok(reader)
 }
+
+pub fn get_writer(output: &str) -> anyhow::Result<Box<dyn Write>, Error> {
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(output)?)
+    };
+    Ok(writer)
+}